@@ -0,0 +1,58 @@
+use std::convert::TryInto;
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+
+use knott::coords::Kind;
+use knott::lookup::{HasId, Labelled, LookupTable};
+
+struct Item {
+    id: Kind,
+    label: String,
+}
+
+impl HasId<Kind> for Item {
+    fn id(&self) -> Kind {
+        self.id
+    }
+}
+
+impl Labelled for Item {
+    fn label(&self) -> &str {
+        &self.label
+    }
+}
+
+/// A table of 52 items with ids `1, 1 + step, 1 + 2 * step, ...`.
+fn table(step: u32) -> (LookupTable<Kind, Item>, Vec<Kind>) {
+    let ids: Vec<Kind> = (0..52).map(|i| (1 + i * step).try_into().unwrap()).collect();
+    let mut table = LookupTable::new();
+    for (i, id) in ids.iter().enumerate() {
+        table.push(Item { id: *id, label: format!("item{}", i) }).ok();
+    }
+    (table, ids)
+}
+
+fn lookup_benchmark(c: &mut Criterion) {
+    for (name, step) in [("dense", 1), ("sparse", 190)] {
+        let (table, ids) = table(step);
+
+        c.bench_function(&format!("find {}", name), |b| {
+            b.iter(|| {
+                for id in &ids {
+                    black_box(table.find(id));
+                }
+            })
+        });
+
+        c.bench_function(&format!("contains_id {}", name), |b| {
+            b.iter(|| {
+                for id in &ids {
+                    black_box(table.contains_id(id));
+                }
+            })
+        });
+    }
+}
+
+criterion_group!(benches, lookup_benchmark);
+criterion_main!(benches);
@@ -0,0 +1,121 @@
+use std::convert::TryInto;
+
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+
+use knott::coords::{QKind, QPos, Region, Suffix};
+use knott::defs::{GameDefBuilder, KindDef, PosDef};
+use knott::specs::GameSpec;
+use knott::state::{Cmd, CreatePieces, MovePieces, State};
+
+fn create_pieces(pos: u32, region: u16, slot: i32, card: i32) -> Cmd {
+    Cmd::CreatePieces(CreatePieces {
+        pos: QPos { pos: pos.try_into().unwrap(), region: Region(region), suffix: Suffix(slot) },
+        kind: QKind { kind: 1.try_into().unwrap(), suffix: Suffix(card) },
+        count: 1,
+    })
+}
+
+/// A dealt game of whist: 13 cards in each of four hands and a discard pile.
+fn dealt_state() -> State {
+    let spec: GameSpec = GameDefBuilder::bld("whist")
+        .min_players(4)
+        .max_players(4)
+        .kind(KindDef::bld("card").suffix_range(1, 52))
+        .pos(PosDef::bld("deck").hidden())
+        .pos(PosDef::bld("hand").hidden().separate())
+        .pos(PosDef::bld("discard").ordered())
+        .build()
+        .try_into()
+        .unwrap();
+
+    let mut state = State::new(&spec);
+    let mut tx = state.start_tx();
+    for card in 1..=52 {
+        let region = (card % 4) as u16;
+        tx.apply(&create_pieces(2, region, 0, card)).unwrap();
+        tx.apply(&create_pieces(3, 0, 0, card)).unwrap();
+    }
+    tx.commit();
+    state
+}
+
+fn clone_benchmark(c: &mut Criterion) {
+    let state = dealt_state();
+    let cmd = create_pieces(2, 1, 0, 1);
+
+    c.bench_function("clone", |b| b.iter(|| black_box(state.clone())));
+
+    c.bench_function("clone and apply", |b| {
+        b.iter(|| {
+            let mut clone = state.clone();
+            let mut tx = clone.start_tx();
+            tx.apply(&cmd).unwrap();
+            tx.commit();
+            black_box(clone)
+        })
+    });
+}
+
+fn apply_benchmark(c: &mut Criterion) {
+    let mut state = dealt_state();
+    let pos = |region| QPos { pos: 2.try_into().unwrap(), region: Region(region), suffix: Suffix(0) };
+    let card = QKind { kind: 1.try_into().unwrap(), suffix: Suffix(5) };
+    let there = Cmd::MovePieces(MovePieces { from: pos(1), to: pos(2), kind: card, count: 1 });
+    let back = Cmd::MovePieces(MovePieces { from: pos(2), to: pos(1), kind: card, count: 1 });
+
+    c.bench_function("apply", |b| {
+        b.iter(|| {
+            let mut tx = state.start_tx();
+            tx.apply(&there).unwrap();
+            tx.apply(&back).unwrap();
+            black_box(tx.commit())
+        })
+    });
+}
+
+/// A pile of `size` different cards and an ordered stack of as many.
+fn filled_state(size: i32) -> State {
+    let spec: GameSpec = GameDefBuilder::bld("piles")
+        .kind(KindDef::bld("card").suffix_range(1, 100_000))
+        .pos(PosDef::bld("pile"))
+        .pos(PosDef::bld("stack").ordered())
+        .build()
+        .try_into()
+        .unwrap();
+
+    let mut state = State::new(&spec);
+    let mut tx = state.start_tx();
+    for card in 1..=size {
+        tx.apply(&create_pieces(1, 0, 0, card)).unwrap();
+        tx.apply(&create_pieces(2, 0, card - 1, card)).unwrap();
+    }
+    tx.commit();
+    state
+}
+
+/// Moving a card onto the end of the stack and back should cost the same
+/// however many pieces the pile and stack already hold.
+fn region_size_benchmark(c: &mut Criterion) {
+    let mut group = c.benchmark_group("apply by region size");
+    for size in &[10, 100, 1000, 10_000] {
+        let mut state = filled_state(*size);
+        let pile = QPos { pos: 1.try_into().unwrap(), region: Region(0), suffix: Suffix(0) };
+        let top = QPos { pos: 2.try_into().unwrap(), region: Region(0), suffix: Suffix(*size) };
+        let card = QKind { kind: 1.try_into().unwrap(), suffix: Suffix(1) };
+        let there = Cmd::MovePieces(MovePieces { from: pile, to: top, kind: card, count: 1 });
+        let back = Cmd::MovePieces(MovePieces { from: top, to: pile, kind: card, count: 1 });
+
+        group.bench_with_input(BenchmarkId::from_parameter(size), size, |b, _| {
+            b.iter(|| {
+                let mut tx = state.start_tx();
+                tx.apply(&there).unwrap();
+                tx.apply(&back).unwrap();
+                black_box(tx.commit())
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, clone_benchmark, apply_benchmark, region_size_benchmark);
+criterion_main!(benches);
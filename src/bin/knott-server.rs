@@ -0,0 +1,42 @@
+use std::convert::TryInto;
+use std::env;
+use std::fs::File;
+use std::net::TcpListener;
+use std::process;
+
+use knott::defs::GameDef;
+use knott::server;
+use knott::specs::GameSpec;
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    if args.len() < 2 || args.len() > 3 {
+        eprintln!("usage: {} <game.yaml> [address]", args[0]);
+        process::exit(2);
+    }
+
+    let file = File::open(&args[1]).unwrap_or_else(|e| {
+        eprintln!("can not open {}: {}", args[1], e);
+        process::exit(1);
+    });
+    let def: GameDef = serde_yaml::from_reader(file).unwrap_or_else(|e| {
+        eprintln!("can not read {}: {}", args[1], e);
+        process::exit(1);
+    });
+    let spec: GameSpec = def.try_into().unwrap_or_else(|e| {
+        eprintln!("invalid game {}: {:?}", args[1], e);
+        process::exit(1);
+    });
+
+    let addr = args.get(2).map(String::as_str).unwrap_or("127.0.0.1:7878");
+    let listener = TcpListener::bind(addr).unwrap_or_else(|e| {
+        eprintln!("can not listen on {}: {}", addr, e);
+        process::exit(1);
+    });
+    println!("listening on {}", addr);
+
+    if let Err(e) = server::serve(spec, listener) {
+        eprintln!("server stopped: {}", e);
+        process::exit(1);
+    }
+}
@@ -0,0 +1,116 @@
+use std::convert::TryInto;
+use std::env;
+use std::fs;
+use std::io;
+use std::io::{BufRead, Write};
+use std::process;
+
+use knott::console::Console;
+use knott::defs::GameDef;
+use knott::lint::{self, Severity};
+use knott::schema;
+use knott::specs::GameSpec;
+
+static USAGE: &str = "\
+usage: knott <game.yaml>         drive a game from the console
+       knott lint <game.yaml>    report problems with a game definition
+       knott fmt <game.yaml>     rewrite a game definition canonically
+       knott schema              print the JSON Schema of game definitions
+       knott diagram <game.yaml> [dot|mermaid]
+                                 draw a game's positions and kinds";
+
+fn read(path: &str) -> String {
+    fs::read_to_string(path).unwrap_or_else(|e| {
+        eprintln!("can not open {}: {}", path, e);
+        process::exit(1);
+    })
+}
+
+fn run_lint(path: &str) {
+    let diagnostics = lint::lint(&read(path));
+    for diagnostic in &diagnostics {
+        println!("{}:{}", path, diagnostic);
+    }
+    if diagnostics.iter().any(|d| d.severity == Severity::Error) {
+        process::exit(1);
+    }
+}
+
+fn run_fmt(path: &str) {
+    match lint::format(&read(path)) {
+        Ok(formatted) => {
+            if let Err(e) = fs::write(path, formatted) {
+                eprintln!("can not write {}: {}", path, e);
+                process::exit(1);
+            }
+        }
+        Err(diagnostic) => {
+            eprintln!("{}:{}", path, diagnostic);
+            process::exit(1);
+        }
+    }
+}
+
+fn load_spec(path: &str) -> GameSpec {
+    let def: GameDef = serde_yaml::from_str(&read(path)).unwrap_or_else(|e| {
+        eprintln!("can not read {}: {}", path, e);
+        process::exit(1);
+    });
+    def.try_into().unwrap_or_else(|e| {
+        eprintln!("invalid game {}: {:?}", path, e);
+        eprintln!("run `knott lint {}` for details", path);
+        process::exit(1);
+    })
+}
+
+fn run_diagram(path: &str, format: &str) {
+    let spec = load_spec(path);
+    match format {
+        "dot" => print!("{}", spec.to_dot()),
+        "mermaid" => print!("{}", spec.to_mermaid()),
+        _ => {
+            eprintln!("unknown diagram format '{}', expected dot or mermaid", format);
+            process::exit(2);
+        }
+    }
+}
+
+fn run_console(path: &str) {
+    let spec = load_spec(path);
+
+    let mut console = Console::new(&spec);
+    let stdin = io::stdin();
+    let mut lines = stdin.lock().lines();
+    loop {
+        print!("> ");
+        io::stdout().flush().unwrap();
+        let line = match lines.next() {
+            Some(Ok(line)) => line,
+            _ => break,
+        };
+        match line.trim() {
+            "quit" | "exit" => break,
+            line => match console.execute(line) {
+                Ok(output) if output.is_empty() => {}
+                Ok(output) => println!("{}", output),
+                Err(e) => println!("error: {}", e),
+            },
+        }
+    }
+}
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    match args.iter().map(String::as_str).collect::<Vec<_>>().as_slice() {
+        ["lint", path] => run_lint(path),
+        ["fmt", path] => run_fmt(path),
+        ["diagram", path] => run_diagram(path, "dot"),
+        ["diagram", path, format] => run_diagram(path, format),
+        ["schema"] => println!("{:#}", schema::game_def_schema()),
+        [path] => run_console(path),
+        _ => {
+            eprintln!("{}", USAGE);
+            process::exit(2);
+        }
+    }
+}
@@ -0,0 +1,242 @@
+//! Checks whether the rows of a game saved under one version of a game
+//! definition still mean the same thing under another.
+
+use std::convert::TryInto;
+
+use crate::coords::Suffix;
+use crate::defs::GameDef;
+use crate::error::Error;
+use crate::specs::{GameSpec, SuffixSpec};
+use crate::state::ExportRow;
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum Incompatibility {
+    /// The row's position id is not in the new definition.
+    PosRemoved,
+    /// The row's position id now belongs to a position with another label.
+    PosRelabelled { from: String, to: String },
+    /// The position stopped (or started) being ordered, so its suffixes
+    /// are no longer slots (or now are).
+    PosOrderingChanged,
+    /// The position is no longer separate, or has fewer players to separate.
+    InvalidRegion,
+    InvalidPosSuffix,
+    /// The row's kind id is not in the new definition.
+    KindRemoved,
+    /// The row's kind id now belongs to a kind with another label.
+    KindRelabelled { from: String, to: String },
+    InvalidKindSuffix,
+    /// A suffix id now belongs to a suffix with another label.
+    SuffixRelabelled { from: String, to: String },
+}
+
+/// A saved row that can not be loaded, with the first reason found.
+#[derive(Debug, PartialEq, Clone)]
+pub struct InvalidRow {
+    pub row: ExportRow,
+    pub reason: Incompatibility,
+}
+
+/// The rows saved under `old` that would become invalid under `new`.
+pub fn check(old: &GameDef, new: &GameDef, rows: &[ExportRow]) -> Result<Vec<InvalidRow>, Error> {
+    let old: GameSpec = old.clone().try_into()?;
+    let new: GameSpec = new.clone().try_into()?;
+    Ok(check_specs(&old, &new, rows))
+}
+
+/// As `check`, for definitions that have already been converted.
+pub fn check_specs(old: &GameSpec, new: &GameSpec, rows: &[ExportRow]) -> Vec<InvalidRow> {
+    rows.iter()
+        .filter_map(|row| {
+            check_row(old, new, row).err().map(|reason| InvalidRow { row: *row, reason })
+        })
+        .collect()
+}
+
+fn check_suffix_label(old: &SuffixSpec, new: &SuffixSpec, suffix: Suffix) -> Result<(), Incompatibility> {
+    match (old.find_label(suffix), new.find_label(suffix)) {
+        (Some(from), Some(to)) if from != to => Err(Incompatibility::SuffixRelabelled {
+            from: from.to_owned(),
+            to: to.to_owned(),
+        }),
+        _ => Ok(()),
+    }
+}
+
+fn check_row(old: &GameSpec, new: &GameSpec, row: &ExportRow) -> Result<(), Incompatibility> {
+    let new_pos = new.pos_specs.find(&row.pos.pos).ok_or(Incompatibility::PosRemoved)?;
+    if let Some(old_pos) = old.pos_specs.find(&row.pos.pos) {
+        if old_pos.label != new_pos.label {
+            return Err(Incompatibility::PosRelabelled {
+                from: old_pos.label.clone(),
+                to: new_pos.label.clone(),
+            });
+        }
+        if old_pos.ordered != new_pos.ordered {
+            return Err(Incompatibility::PosOrderingChanged);
+        }
+        if !new_pos.ordered {
+            check_suffix_label(&old_pos.suffixes, &new_pos.suffixes, row.pos.suffix)?;
+        }
+    }
+
+    let region = row.pos.region.0;
+    let region_valid = if new_pos.separate {
+        region < new.max_players() as u16
+    } else {
+        region == 0
+    };
+    if !region_valid {
+        return Err(Incompatibility::InvalidRegion);
+    }
+    let suffix_valid = if new_pos.ordered {
+        row.pos.suffix.0 >= 0
+    } else {
+        new_pos.suffixes.is_valid(row.pos.suffix)
+    };
+    if !suffix_valid {
+        return Err(Incompatibility::InvalidPosSuffix);
+    }
+
+    let new_kind = new.kind_specs.find(&row.kind.kind).ok_or(Incompatibility::KindRemoved)?;
+    if let Some(old_kind) = old.kind_specs.find(&row.kind.kind) {
+        if old_kind.label != new_kind.label {
+            return Err(Incompatibility::KindRelabelled {
+                from: old_kind.label.clone(),
+                to: new_kind.label.clone(),
+            });
+        }
+        check_suffix_label(&old_kind.suffixes, &new_kind.suffixes, row.kind.suffix)?;
+    }
+    if !new_kind.suffixes.is_valid(row.kind.suffix) {
+        return Err(Incompatibility::InvalidKindSuffix);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::coords::{QKind, QPos, Region};
+    use crate::defs::{GameDefBuilder, KindDef, PosDef, SuffixDef, SuffixRangeDef};
+
+    fn whist() -> GameDef {
+        GameDefBuilder::bld("whist")
+            .kind(KindDef::bld("card").suffix_range(1, 52))
+            .kind(
+                KindDef::bld("suit")
+                    .suffix(SuffixDef::bld("hearts"))
+                    .suffix(SuffixDef::bld("clubs")),
+            )
+            .pos(PosDef::bld("deck").hidden())
+            .pos(PosDef::bld("hand").hidden().separate())
+            .build()
+    }
+
+    fn row(pos: u32, region: u16, kind: u32, suffix: i32) -> ExportRow {
+        ExportRow {
+            pos: QPos { pos: pos.try_into().unwrap(), region: Region(region), suffix: Suffix(0) },
+            kind: QKind { kind: kind.try_into().unwrap(), suffix: Suffix(suffix) },
+            count: 1,
+        }
+    }
+
+    #[test]
+    fn unchanged_definition_is_compatible() {
+        let rows = [row(1, 0, 1, 7), row(2, 1, 2, 2)];
+        assert_eq!(Ok(vec![]), check(&whist(), &whist(), &rows));
+    }
+
+    #[test]
+    fn inserting_positional_kind_breaks_saved_rows() {
+        let edited = GameDefBuilder::bld("whist")
+            .kind(KindDef::bld("joker"))
+            .kind(KindDef::bld("card").suffix_range(1, 52))
+            .kind(
+                KindDef::bld("suit")
+                    .suffix(SuffixDef::bld("hearts"))
+                    .suffix(SuffixDef::bld("clubs")),
+            )
+            .pos(PosDef::bld("deck").hidden())
+            .pos(PosDef::bld("hand").hidden().separate())
+            .build();
+
+        let rows = [row(1, 0, 1, 7)];
+        assert_eq!(
+            Ok(vec![InvalidRow {
+                row: rows[0],
+                reason: Incompatibility::KindRelabelled { from: "card".to_owned(), to: "joker".to_owned() },
+            }]),
+            check(&whist(), &edited, &rows)
+        );
+    }
+
+    #[test]
+    fn stable_ids_keep_saved_rows_valid() {
+        let mut edited: GameDef = serde_yaml::from_str(
+            "
+label: whist
+min_players: 2
+max_players: 2
+kind_defs:
+  - label: joker
+  - label: card
+    suffix_range: {min: 1, max: 52}
+  - label: suit
+    suffixes: [{label: spades}, {label: hearts}, {label: clubs}]
+pos_defs:
+  - {label: deck, hidden: true}
+  - {label: hand, hidden: true, separate: true}
+",
+        )
+        .unwrap();
+        edited.assign_ids_from(&whist());
+
+        let ids: Vec<u32> = edited.kind_defs.iter().map(|d| d.id.unwrap()).collect();
+        assert_eq!(vec![3, 1, 2], ids);
+        let suits: Vec<u32> = edited.kind_defs[2].suffixes.iter().map(|d| d.id.unwrap()).collect();
+        assert_eq!(vec![3, 1, 2], suits);
+
+        let rows = [row(1, 0, 1, 7), row(2, 1, 2, 2)];
+        assert_eq!(Ok(vec![]), check(&whist(), &edited, &rows));
+    }
+
+    #[test]
+    fn removed_ids_are_not_reused() {
+        let mut edited = whist();
+        edited.kind_defs.remove(1);
+        edited.kind_defs.push(KindDef {
+            label: "joker".to_owned(),
+            id: None,
+            aliases: vec![],
+            names: Default::default(),
+            attributes: Default::default(),
+            suffix_range: None,
+            suffixes: vec![],
+            suffix_product: None,
+        });
+        edited.assign_ids_from(&whist());
+        assert_eq!(Some(3), edited.kind_defs[1].id);
+
+        let rows = [row(1, 0, 2, 1)];
+        assert_eq!(
+            Ok(vec![InvalidRow { row: rows[0], reason: Incompatibility::KindRemoved }]),
+            check(&whist(), &edited, &rows)
+        );
+    }
+
+    #[test]
+    fn reports_rows_outside_new_ranges_and_regions() {
+        let mut edited = whist();
+        edited.kind_defs[0].suffix_range = Some(SuffixRangeDef { min: 1, max: 40 });
+        edited.pos_defs[1].separate = false;
+
+        let rows = [row(1, 0, 1, 45), row(2, 1, 1, 3), row(2, 0, 1, 4)];
+        let reasons: Vec<Incompatibility> = check(&whist(), &edited, &rows)
+            .unwrap()
+            .into_iter()
+            .map(|r| r.reason)
+            .collect();
+        assert_eq!(vec![Incompatibility::InvalidKindSuffix, Incompatibility::InvalidRegion], reasons);
+    }
+}
@@ -0,0 +1,523 @@
+//! An interactive console for driving a game by label, for example
+//! `create deck card:1..52`, `move deck -> hand[2] x13` or `show hand[2]`.
+
+use std::convert::TryFrom;
+use std::fmt;
+
+use crate::coords::{QKind, QPos, Region, Suffix};
+use crate::diff::Diff;
+use crate::lookup::LabelCase;
+pub use crate::render::render_table;
+use crate::specs::{GameSpec, SuffixSpec};
+use crate::state::{Cmd, CmdError, CreatePieces, ExportRow, MovePieces, State};
+
+pub static HELP: &str = "\
+create <pos> <kind>[:<suffix>|:<min>..<max>] [x<count>]
+move <pos> [<kind>] -> <pos> [x<count>]
+show [<pos>]
+board
+undo
+help
+
+positions are written label, label[region] or label[region]:suffix";
+
+#[derive(Debug, PartialEq)]
+pub enum ConsoleError {
+    UnknownCommand(String),
+    Usage(&'static str),
+    /// A label that names nothing, with the nearest one that does.
+    UnknownPos(String, Option<String>),
+    UnknownKind(String, Option<String>),
+    UnknownSuffix(String, Option<String>),
+    InvalidCount(String),
+    NotEnoughPieces(String),
+    Cmd(CmdError),
+    NothingToUndo,
+}
+
+fn write_unknown(f: &mut fmt::Formatter, noun: &str, label: &str, suggestion: &Option<String>) -> fmt::Result {
+    write!(f, "unknown {} '{}'", noun, label)?;
+    match suggestion {
+        Some(suggestion) => write!(f, ", did you mean '{}'?", suggestion),
+        None => Ok(()),
+    }
+}
+
+impl fmt::Display for ConsoleError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConsoleError::UnknownCommand(cmd) => write!(f, "unknown command '{}'", cmd),
+            ConsoleError::Usage(usage) => write!(f, "usage: {}", usage),
+            ConsoleError::UnknownPos(label, suggestion) => write_unknown(f, "position", label, suggestion),
+            ConsoleError::UnknownKind(label, suggestion) => write_unknown(f, "kind", label, suggestion),
+            ConsoleError::UnknownSuffix(label, suggestion) => write_unknown(f, "suffix", label, suggestion),
+            ConsoleError::InvalidCount(count) => write!(f, "invalid count '{}'", count),
+            ConsoleError::NotEnoughPieces(pos) => write!(f, "not enough pieces at '{}'", pos),
+            ConsoleError::Cmd(e) => write!(f, "command failed: {:?}", e),
+            ConsoleError::NothingToUndo => write!(f, "nothing to undo"),
+        }
+    }
+}
+
+impl From<CmdError> for ConsoleError {
+    fn from(e: CmdError) -> Self {
+        ConsoleError::Cmd(e)
+    }
+}
+
+pub struct Console<'a> {
+    spec: &'a GameSpec,
+    state: State,
+    history: Vec<Diff>,
+}
+
+/// Splits `label[region]:suffix` into its parts.
+fn split_pos_ref(text: &str) -> (&str, Option<&str>, Option<&str>) {
+    let (rest, suffix) = match text.find(':') {
+        Some(i) => (&text[..i], Some(&text[i + 1..])),
+        None => (text, None),
+    };
+    match (rest.find('['), rest.strip_suffix(']')) {
+        (Some(i), Some(inner)) => (&rest[..i], Some(&inner[i + 1..]), suffix),
+        _ => (rest, None, suffix),
+    }
+}
+
+fn resolve_suffix(suffixes: &SuffixSpec, text: &str) -> Result<Suffix, ConsoleError> {
+    let suffix = match text.parse::<i32>() {
+        Ok(n) => Some(Suffix(n)).filter(|s| suffixes.is_valid(*s)),
+        Err(_) => suffixes.find_by_label_with(text, LabelCase::Insensitive),
+    };
+    suffix.ok_or_else(|| {
+        let suggestion = suffixes.suggest_label(text).map(str::to_owned);
+        ConsoleError::UnknownSuffix(text.to_owned(), suggestion)
+    })
+}
+
+/// Parses `x<count>`. Counts fit an `i32` so that they can number slots.
+fn parse_count(text: &str) -> Result<u32, ConsoleError> {
+    text.strip_prefix('x')
+        .and_then(|n| n.parse().ok())
+        .filter(|n| *n > 0 && *n <= i32::MAX as u32)
+        .ok_or_else(|| ConsoleError::InvalidCount(text.to_owned()))
+}
+
+/// The slot after `count` pieces placed from `slot`.
+fn slot_after(slot: Suffix, count: u32) -> Result<Suffix, ConsoleError> {
+    i32::try_from(count).ok()
+        .and_then(|count| slot.0.checked_add(count))
+        .map(Suffix)
+        .ok_or_else(|| ConsoleError::InvalidCount(format!("x{}", count)))
+}
+
+impl<'a> Console<'a> {
+    pub fn new(spec: &'a GameSpec) -> Console<'a> {
+        Console {
+            spec,
+            state: State::new(spec),
+            history: Vec::new(),
+        }
+    }
+
+    pub fn state(&self) -> &State {
+        &self.state
+    }
+
+    /// Runs one line of input, returning what should be printed.
+    pub fn execute(&mut self, line: &str) -> Result<String, ConsoleError> {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        match words.split_first() {
+            None => Ok(String::new()),
+            Some((&"create", args)) => self.create(args),
+            Some((&"move", args)) => self.move_pieces(args),
+            Some((&"show", args)) => self.show(args),
+            Some((&"board", [])) => Ok(self.state.display(self.spec).to_string()),
+            Some((&"undo", [])) => self.undo(),
+            Some((&"help", [])) => Ok(HELP.to_owned()),
+            Some((cmd, _)) => Err(ConsoleError::UnknownCommand((*cmd).to_owned())),
+        }
+    }
+
+    fn resolve_pos(&self, text: &str) -> Result<QPos, ConsoleError> {
+        let (label, region, suffix) = split_pos_ref(text);
+        let spec = self.spec
+            .find_pos_by_label_with(label, LabelCase::Insensitive)
+            .ok_or_else(|| {
+                let suggestion = self.spec.suggest_pos_label(label).map(str::to_owned);
+                ConsoleError::UnknownPos(label.to_owned(), suggestion)
+            })?;
+        let region = match region {
+            Some(r) => Region(r.parse().map_err(|_| ConsoleError::UnknownPos(text.to_owned(), None))?),
+            None => Region(0),
+        };
+        let suffix = match suffix {
+            Some(s) if spec.ordered => Suffix(s.parse().map_err(|_| ConsoleError::UnknownSuffix(s.to_owned(), None))?),
+            Some(s) => resolve_suffix(&spec.suffixes, s)?,
+            None => Suffix(0),
+        };
+        Ok(QPos { pos: spec.id, region, suffix })
+    }
+
+    /// Resolves `label`, `label:suffix` or `label:min..max` to the kinds it names.
+    fn resolve_kinds(&self, text: &str) -> Result<Vec<QKind>, ConsoleError> {
+        let (label, suffix) = match text.find(':') {
+            Some(i) => (&text[..i], Some(&text[i + 1..])),
+            None => (text, None),
+        };
+        let spec = self.spec
+            .find_kind_by_label_with(label, LabelCase::Insensitive)
+            .ok_or_else(|| {
+                let suggestion = self.spec.suggest_kind_label(label).map(str::to_owned);
+                ConsoleError::UnknownKind(label.to_owned(), suggestion)
+            })?;
+        let qkind = |suffix| QKind { kind: spec.id, suffix };
+
+        match suffix {
+            None if spec.suffixes.is_valid(Suffix(0)) => Ok(vec![qkind(Suffix(0))]),
+            None => Err(ConsoleError::UnknownSuffix(text.to_owned(), None)),
+            Some(suffix) => match suffix.find("..") {
+                Some(i) => {
+                    let (from, to) = (&suffix[..i], &suffix[i + 2..]);
+                    let min = resolve_suffix(&spec.suffixes, from)?;
+                    let max = resolve_suffix(&spec.suffixes, to)?;
+                    if min.0 > max.0 {
+                        let reversed = format!("{}..{}", to, from);
+                        return Err(ConsoleError::UnknownSuffix(suffix.to_owned(), Some(reversed)));
+                    }
+                    Ok((min.0..=max.0)
+                        .map(Suffix)
+                        .filter(|s| spec.suffixes.is_valid(*s))
+                        .map(qkind)
+                        .collect())
+                }
+                None => Ok(vec![qkind(resolve_suffix(&spec.suffixes, suffix)?)]),
+            },
+        }
+    }
+
+    fn rows_at(&self, pos: QPos, whole_region: bool) -> Vec<ExportRow> {
+        let mut rows: Vec<ExportRow> = self.state.export_rows()
+            .into_iter()
+            .filter(|r| r.pos.pos == pos.pos && r.pos.region == pos.region)
+            .filter(|r| whole_region || r.pos.suffix == pos.suffix)
+            .collect();
+        rows.sort();
+        rows
+    }
+
+    fn is_ordered(&self, pos: QPos) -> bool {
+        self.spec.pos_specs.find(&pos.pos).is_some_and(|s| s.ordered)
+    }
+
+    /// Applies `cmds` as one undoable step, leaving the state untouched if any fail.
+    fn run(&mut self, cmds: &[Cmd]) -> Result<String, ConsoleError> {
+        let mut tx = self.state.start_tx();
+        if let Err(e) = cmds.iter().try_for_each(|cmd| tx.apply(cmd)) {
+            tx.rollback();
+            return Err(e.into());
+        }
+        let diff = tx.commit();
+        let changed = diff.iter().count();
+        if !diff.is_empty() {
+            self.history.push(diff);
+        }
+        Ok(format!("{} position(s) changed", changed))
+    }
+
+    fn create(&mut self, args: &[&str]) -> Result<String, ConsoleError> {
+        let usage = ConsoleError::Usage("create <pos> <kind> [x<count>]");
+        let (pos, kinds, count) = match args {
+            [pos, kind] => (*pos, *kind, 1),
+            [pos, kind, count] => (*pos, *kind, parse_count(count)?),
+            _ => return Err(usage),
+        };
+        let mut pos = self.resolve_pos(pos)?;
+        let kinds = self.resolve_kinds(kinds)?;
+
+        if self.is_ordered(pos) && split_pos_ref(args[0]).2.is_none() {
+            pos.suffix = Suffix(self.rows_at(pos, true).len() as i32);
+        }
+        let mut cmds = Vec::with_capacity(kinds.len());
+        for kind in kinds {
+            cmds.push(Cmd::CreatePieces(CreatePieces { pos, kind, count }));
+            if self.is_ordered(pos) {
+                pos.suffix = slot_after(pos.suffix, count)?;
+            }
+        }
+        self.run(&cmds)
+    }
+
+    fn move_pieces(&mut self, args: &[&str]) -> Result<String, ConsoleError> {
+        let usage = ConsoleError::Usage("move <pos> [<kind>] -> <pos> [x<count>]");
+        let arrow = args.iter().position(|a| *a == "->").ok_or(usage)?;
+        let (source, target) = args.split_at(arrow);
+        let (from, kind) = match source {
+            [from] => (*from, None),
+            [from, kind] => (*from, Some(*kind)),
+            _ => return Err(ConsoleError::Usage("move <pos> [<kind>] -> <pos> [x<count>]")),
+        };
+        let (to, count) = match target {
+            [_, to] => (*to, 1),
+            [_, to, count] => (*to, parse_count(count)?),
+            _ => return Err(ConsoleError::Usage("move <pos> [<kind>] -> <pos> [x<count>]")),
+        };
+
+        let from = self.resolve_pos(from)?;
+        let from_ordered = self.is_ordered(from);
+        let mut to_pos = self.resolve_pos(to)?;
+        if self.is_ordered(to_pos) && split_pos_ref(to).2.is_none() {
+            to_pos.suffix = Suffix(self.rows_at(to_pos, true).len() as i32);
+        }
+
+        let kinds = match kind {
+            Some(kind) => self.resolve_kinds(kind)?,
+            None => Vec::new(),
+        };
+        let mut available: Vec<ExportRow> = self.rows_at(from, from_ordered)
+            .into_iter()
+            .filter(|r| kinds.is_empty() || kinds.contains(&r.kind))
+            .collect();
+        if from_ordered {
+            // Deal from the top of an ordered position.
+            available.reverse();
+        }
+
+        let mut cmds = Vec::new();
+        let mut remaining = count;
+        for row in available {
+            if remaining == 0 {
+                break;
+            }
+            let n = row.count.min(remaining);
+            cmds.push(Cmd::MovePieces(MovePieces { from: row.pos, to: to_pos, kind: row.kind, count: n }));
+            remaining -= n;
+            if self.is_ordered(to_pos) {
+                to_pos.suffix = slot_after(to_pos.suffix, n)?;
+            }
+        }
+        if remaining > 0 {
+            return Err(ConsoleError::NotEnoughPieces(args[0].to_owned()));
+        }
+        self.run(&cmds)
+    }
+
+    fn show(&self, args: &[&str]) -> Result<String, ConsoleError> {
+        let rows = match args {
+            [] => {
+                let mut rows = self.state.export_rows();
+                rows.sort();
+                rows
+            }
+            [pos] => {
+                let whole_region = split_pos_ref(pos).2.is_none();
+                self.rows_at(self.resolve_pos(pos)?, whole_region)
+            }
+            _ => return Err(ConsoleError::Usage("show [<pos>]")),
+        };
+        let cells: Vec<Vec<String>> = rows.iter()
+            .map(|row| vec![
+                self.spec.describe_pos(&row.pos),
+                self.spec.describe_kind(&row.kind),
+                row.count.to_string(),
+            ])
+            .collect();
+        Ok(render_table(&["pos", "kind", "count"], &cells))
+    }
+
+    fn undo(&mut self) -> Result<String, ConsoleError> {
+        let diff = self.history.pop().ok_or(ConsoleError::NothingToUndo)?;
+        self.state.apply_diff(&diff.inverse())?;
+        Ok(format!("{} position(s) restored", diff.iter().count()))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::defs::{GameDefBuilder, KindDef, PosDef, SuffixDef};
+    use std::convert::TryInto;
+
+    fn whist() -> GameSpec {
+        GameDefBuilder::bld("whist")
+            .kind(KindDef::bld("card").suffix_range(1, 52))
+            .kind(
+                KindDef::bld("suit")
+                    .suffix(SuffixDef::bld("hearts").alias("♥"))
+                    .suffix(SuffixDef::bld("clubs").alias("♣")),
+            )
+            .kind(
+                KindDef::bld("chip")
+                    .suffix(SuffixDef::bld("white").id(1))
+                    .suffix(SuffixDef::bld("red").id(5))
+                    .suffix(SuffixDef::bld("blue").id(9)),
+            )
+            .pos(PosDef::bld("deck").hidden())
+            .pos(PosDef::bld("hand").hidden().separate())
+            .pos(PosDef::bld("pile").ordered())
+            .pos(PosDef::bld("trump"))
+            .build()
+            .try_into()
+            .unwrap()
+    }
+
+    fn count(console: &Console) -> u32 {
+        console.state().export_rows().iter().map(|r| r.count).sum()
+    }
+
+    #[test]
+    fn can_create_move_and_undo() {
+        let spec = whist();
+        let mut console = Console::new(&spec);
+
+        console.execute("create deck card:1..52").unwrap();
+        assert_eq!(52, count(&console));
+
+        console.execute("move deck -> hand[2] x13").unwrap();
+        assert_eq!(
+            "pos     | kind    | count\n\
+             --------+---------+------\n\
+             hand[2] | card:1  | 1",
+            console.execute("show hand[2]").unwrap().lines().take(3).collect::<Vec<_>>().join("\n")
+        );
+        assert_eq!(13 + 2, console.execute("show hand[2]").unwrap().lines().count());
+
+        console.execute("move hand[2] card:5 -> trump").unwrap();
+        console.execute("create trump suit:hearts").unwrap();
+        assert_eq!(
+            "pos   | kind        | count\n\
+             ------+-------------+------\n\
+             trump | card:5      | 1\n\
+             trump | suit:hearts | 1",
+            console.execute("show trump").unwrap()
+        );
+
+        console.execute("undo").unwrap();
+        console.execute("undo").unwrap();
+        console.execute("undo").unwrap();
+        assert_eq!(
+            "pos | kind | count\n----+------+------",
+            console.execute("show hand[2]").unwrap()
+        );
+        assert_eq!(52, count(&console));
+    }
+
+    #[test]
+    fn deals_from_top_of_ordered_positions() {
+        let spec = whist();
+        let mut console = Console::new(&spec);
+
+        console.execute("create pile card:1..3").unwrap();
+        console.execute("move pile -> hand[1] x2").unwrap();
+
+        let hand = console.execute("show hand[1]").unwrap();
+        assert!(hand.contains("card:2"));
+        assert!(hand.contains("card:3"));
+        assert_eq!(
+            "pos    | kind   | count\n\
+             -------+--------+------\n\
+             pile:0 | card:1 | 1",
+            console.execute("show pile").unwrap()
+        );
+    }
+
+    #[test]
+    fn board_shows_positions_by_region() {
+        let spec = whist();
+        let mut console = Console::new(&spec);
+
+        console.execute("create pile card:1..2").unwrap();
+        console.execute("create hand[1] suit:hearts x2").unwrap();
+
+        let board = console.execute("board").unwrap();
+        assert!(board.contains("hand  |                  | suit:hearts x2"), "{}", board);
+        assert!(board.contains("pile  | [card:1, card:2]"), "{}", board);
+    }
+
+    #[test]
+    fn accepts_aliases_in_any_case() {
+        let spec = whist();
+        let mut console = Console::new(&spec);
+
+        console.execute("create Trump suit:Hearts").unwrap();
+        console.execute("create TRUMP SUIT:♥").unwrap();
+        assert_eq!(
+            "pos   | kind        | count\n\
+             ------+-------------+------\n\
+             trump | suit:hearts | 2",
+            console.execute("show trump").unwrap()
+        );
+    }
+
+    #[test]
+    fn reports_errors_by_label() {
+        let spec = whist();
+        let mut console = Console::new(&spec);
+
+        assert_eq!(
+            Err(ConsoleError::UnknownPos("hnad".to_owned(), Some("hand".to_owned()))),
+            console.execute("show hnad")
+        );
+        assert_eq!(
+            Err(ConsoleError::UnknownKind("crad".to_owned(), Some("card".to_owned()))),
+            console.execute("create deck crad:1")
+        );
+        assert_eq!(Err(ConsoleError::UnknownPos("river".to_owned(), None)), console.execute("show river"));
+        assert_eq!(Err(ConsoleError::UnknownSuffix("53".to_owned(), None)), console.execute("create deck card:53"));
+        assert_eq!(Err(ConsoleError::NotEnoughPieces("deck".to_owned())), console.execute("move deck -> trump"));
+        assert_eq!(Err(ConsoleError::NothingToUndo), console.execute("undo"));
+        assert_eq!(Err(ConsoleError::UnknownCommand("shuffle".to_owned())), console.execute("shuffle deck"));
+        assert_eq!(
+            "unknown position 'hnad', did you mean 'hand'?",
+            console.execute("show hnad").unwrap_err().to_string()
+        );
+        assert_eq!(
+            "unknown suffix 'harts', did you mean 'hearts'?",
+            console.execute("create trump suit:harts").unwrap_err().to_string()
+        );
+        assert_eq!("unknown position 'river'", console.execute("show river").unwrap_err().to_string());
+    }
+
+    #[test]
+    fn suffix_ranges_skip_gaps_and_must_ascend() {
+        let spec = whist();
+        let mut console = Console::new(&spec);
+
+        console.execute("create trump chip:white..blue").unwrap();
+        assert_eq!(3, count(&console));
+        assert_eq!(
+            "unknown suffix 'blue..white', did you mean 'white..blue'?",
+            console.execute("create trump chip:blue..white").unwrap_err().to_string()
+        );
+        assert_eq!(3, count(&console));
+    }
+
+    #[test]
+    fn failed_commands_leave_state_untouched() {
+        let spec = whist();
+        let mut console = Console::new(&spec);
+
+        console.execute("create deck card:1..2").unwrap();
+        assert!(console.execute("move deck -> pile:3 x2").is_err());
+        assert_eq!(
+            Err(ConsoleError::NothingToUndo),
+            console.execute("undo").and_then(|_| console.execute("undo"))
+        );
+        assert_eq!(0, count(&console));
+    }
+
+    #[test]
+    fn counts_must_number_slots() {
+        let spec = whist();
+        let mut console = Console::new(&spec);
+
+        assert_eq!(
+            Err(ConsoleError::InvalidCount("x3000000000".to_owned())),
+            console.execute("create pile card:1..3 x3000000000")
+        );
+        assert_eq!(
+            Err(ConsoleError::InvalidCount("x2000000000".to_owned())),
+            console.execute("create pile card:1..3 x2000000000")
+        );
+        assert_eq!(0, count(&console));
+    }
+}
@@ -1,12 +1,16 @@
+use serde::{Deserialize, Serialize};
 use std::convert::TryFrom;
 use std::fmt;
 use std::num::NonZeroU16;
 
-#[derive(Hash, Eq, PartialEq, Ord, PartialOrd, Copy, Clone)]
+use crate::lookup::DenseId;
+
+#[derive(Hash, Eq, PartialEq, Ord, PartialOrd, Copy, Clone, Serialize, Deserialize)]
+#[serde(try_from = "u32", into = "u32")]
 pub struct Kind(NonZeroU16);
 
-static MIN_KIND: u32 = 1;
-static MAX_KIND: u32 = 9999;
+pub(crate) static MIN_KIND: u32 = 1;
+pub(crate) static MAX_KIND: u32 = 9999;
 
 #[derive(Debug, Eq, PartialEq)]
 pub struct InvalidKind(pub u32);
@@ -26,6 +30,18 @@ impl TryFrom<u32> for Kind {
     }
 }
 
+impl From<Kind> for u32 {
+    fn from(kind: Kind) -> Self {
+        kind.as_u32()
+    }
+}
+
+impl fmt::Display for InvalidKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "invalid kind {}", self.0)
+    }
+}
+
 impl fmt::Debug for Kind {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "{:03}", self.0.get())
@@ -44,7 +60,17 @@ impl Kind {
     }
 }
 
-#[derive(Hash, Eq, PartialEq, Ord, PartialOrd, Copy, Clone)]
+impl DenseId for Kind {
+    fn dense_index(&self) -> Option<usize> {
+        Some(self.0.get() as usize)
+    }
+
+    fn from_dense_index(index: usize) -> Self {
+        Kind(NonZeroU16::new(index as u16).expect("kinds are never at index 0"))
+    }
+}
+
+#[derive(Hash, Eq, PartialEq, Ord, PartialOrd, Copy, Clone, Serialize, Deserialize)]
 pub struct QKind {
     pub kind: Kind,
     pub suffix: Suffix,
@@ -62,11 +88,12 @@ impl fmt::Display for QKind {
     }
 }
 
-#[derive(Hash, Eq, PartialEq, Ord, PartialOrd, Copy, Clone)]
+#[derive(Hash, Eq, PartialEq, Ord, PartialOrd, Copy, Clone, Serialize, Deserialize)]
+#[serde(try_from = "u32", into = "u32")]
 pub struct Pos(NonZeroU16);
 
-static MIN_POS: u32 = 1;
-static MAX_POS: u32 = 9999;
+pub(crate) static MIN_POS: u32 = 1;
+pub(crate) static MAX_POS: u32 = 9999;
 
 #[derive(Debug, PartialEq)]
 pub struct InvalidPos(pub u32);
@@ -86,6 +113,18 @@ impl TryFrom<u32> for Pos {
     }
 }
 
+impl From<Pos> for u32 {
+    fn from(pos: Pos) -> Self {
+        pos.as_u32()
+    }
+}
+
+impl fmt::Display for InvalidPos {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "invalid pos {}", self.0)
+    }
+}
+
 impl fmt::Debug for Pos {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "{:03}", self.0.get())
@@ -98,9 +137,36 @@ impl fmt::Display for Pos {
     }
 }
 
-#[derive(Hash, Eq, Ord, PartialOrd, PartialEq, Copy, Clone)]
+impl Pos {
+    pub fn as_u32(&self) -> u32 {
+        self.0.get() as u32
+    }
+}
+
+impl DenseId for Pos {
+    fn dense_index(&self) -> Option<usize> {
+        Some(self.0.get() as usize)
+    }
+
+    fn from_dense_index(index: usize) -> Self {
+        Pos(NonZeroU16::new(index as u16).expect("positions are never at index 0"))
+    }
+}
+
+#[derive(Hash, Eq, Ord, PartialOrd, PartialEq, Copy, Clone, Serialize, Deserialize)]
 pub struct Suffix(pub i32);
 
+/// Negative suffixes have no index, so tables holding them are sparse.
+impl DenseId for Suffix {
+    fn dense_index(&self) -> Option<usize> {
+        usize::try_from(self.0).ok()
+    }
+
+    fn from_dense_index(index: usize) -> Self {
+        Suffix(index as i32)
+    }
+}
+
 impl fmt::Debug for Suffix {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "{:+}", self.0)
@@ -113,7 +179,7 @@ impl fmt::Display for Suffix {
     }
 }
 
-#[derive(Hash, Eq, PartialEq, Ord, PartialOrd, Copy, Clone)]
+#[derive(Hash, Eq, PartialEq, Ord, PartialOrd, Copy, Clone, Serialize, Deserialize)]
 pub struct Region(pub u16);
 
 impl fmt::Debug for Region {
@@ -128,7 +194,7 @@ impl fmt::Display for Region {
     }
 }
 
-#[derive(Eq, PartialEq, Ord, PartialOrd, Copy, Clone)]
+#[derive(Eq, PartialEq, Ord, PartialOrd, Copy, Clone, Serialize, Deserialize)]
 pub struct QPos {
     pub pos: Pos,
     pub region: Region,
@@ -183,6 +249,7 @@ mod test {
     }
 
     #[test]
+    #[allow(clippy::legacy_numeric_constants)]
     fn check_max_kind_is_a_valid_u16() {
         assert!(std::u16::MAX as u32 > MAX_KIND);
     }
@@ -211,6 +278,12 @@ mod test {
         );
     }
 
+    #[test]
+    fn can_convert_pos_to_u32() {
+        let t: Pos = 56.try_into().unwrap();
+        assert_eq!(56, t.as_u32());
+    }
+
     #[test]
     fn can_convert_zero_to_pos() {
         let r: Result<Pos, InvalidPos> = 0.try_into();
@@ -224,6 +297,7 @@ mod test {
     }
 
     #[test]
+    #[allow(clippy::legacy_numeric_constants)]
     fn check_max_pos_is_a_valid_u16() {
         assert!(std::u16::MAX as u32 > MAX_POS);
     }
@@ -234,6 +308,15 @@ mod test {
         assert_eq!("045", format!("{:?}", pos));
     }
 
+    #[test]
+    fn kind_serializes_as_number() {
+        let kind: Kind = 45.try_into().unwrap();
+        assert_eq!("45", serde_json::to_string(&kind).unwrap());
+        assert_eq!(kind, serde_json::from_str("45").unwrap());
+        assert!(serde_json::from_str::<Kind>("0").is_err());
+        assert!(serde_json::from_str::<Kind>("10000").is_err());
+    }
+
     #[test]
     fn pos_display_is_number() {
         let pos: Pos = 45.try_into().unwrap();
@@ -1,4 +1,5 @@
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::convert::TryInto;
 
 fn default_suffix_range() -> Option<SuffixRangeDef> {
@@ -13,21 +14,114 @@ fn ignore_if_false(value: &bool) -> bool {
     value == &false
 }
 
-#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
 pub struct SuffixRangeDef {
     pub min: i32,
     pub max: i32,
 }
 
-#[derive(Debug, PartialEq, Serialize, Deserialize)]
+/// The value of an attribute of a kind or suffix, such as a card's point
+/// value or colour. In YAML it is written as a plain scalar.
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum AttributeValue {
+    Bool(bool),
+    Int(i64),
+    Str(String),
+}
+
+impl AttributeValue {
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            AttributeValue::Bool(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    pub fn as_int(&self) -> Option<i64> {
+        match self {
+            AttributeValue::Int(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            AttributeValue::Str(value) => Some(value),
+            _ => None,
+        }
+    }
+}
+
+impl From<bool> for AttributeValue {
+    fn from(value: bool) -> Self {
+        AttributeValue::Bool(value)
+    }
+}
+
+impl From<i64> for AttributeValue {
+    fn from(value: i64) -> Self {
+        AttributeValue::Int(value)
+    }
+}
+
+impl From<i32> for AttributeValue {
+    fn from(value: i32) -> Self {
+        AttributeValue::Int(value.into())
+    }
+}
+
+impl From<&str> for AttributeValue {
+    fn from(value: &str) -> Self {
+        AttributeValue::Str(value.to_owned())
+    }
+}
+
+impl From<String> for AttributeValue {
+    fn from(value: String) -> Self {
+        AttributeValue::Str(value)
+    }
+}
+
+/// One factor of a `SuffixProductDef`, such as the suits of a deck.
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct DimensionDef {
+    pub label: String,
+    pub values: Vec<String>,
+}
+
+/// A suffix for every combination of one value from each dimension, in
+/// order with the last dimension varying fastest. Suffixes are numbered
+/// from 1 and labelled with their values joined by `-`, so suits by ranks
+/// give `hearts-ace` (1), `hearts-2` (2) and so on. Dimension labels must
+/// differ, and there may be at most `MAX_SUFFIX_PRODUCT` combinations.
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct SuffixProductDef {
+    pub dimensions: Vec<DimensionDef>,
+}
+
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
 pub struct SuffixDef {
     pub label: String,
-    pub id: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<u32>,
+    /// Other labels the suffix can be found by, such as `Hearts` or `♥`.
+    #[serde(default = "Vec::new", skip_serializing_if = "Vec::is_empty")]
+    pub aliases: Vec<String>,
+    /// Names to show players by locale, such as `fr: Cœurs`.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub names: BTreeMap<String, String>,
+    /// Attributes of pieces with this suffix, overriding those of the kind.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub attributes: BTreeMap<String, AttributeValue>,
 }
 
 pub struct SuffixDefBuilder {
     pub label: String,
     pub id: Option<u32>,
+    pub aliases: Vec<String>,
+    pub names: BTreeMap<String, String>,
+    pub attributes: BTreeMap<String, AttributeValue>,
 }
 
 impl SuffixDef {
@@ -35,24 +129,64 @@ impl SuffixDef {
         SuffixDefBuilder {
             label: label.as_ref().to_owned(),
             id: None,
+            aliases: Vec::new(),
+            names: BTreeMap::new(),
+            attributes: BTreeMap::new(),
         }
     }
 }
 
 impl SuffixDefBuilder {
-    fn build(self, next_id: u32) -> SuffixDef {
+    pub fn id(mut self, id: u32) -> Self {
+        self.id = Some(id);
+        self
+    }
+
+    pub fn alias<T: AsRef<str>>(mut self, alias: T) -> Self {
+        self.aliases.push(alias.as_ref().to_owned());
+        self
+    }
+
+    /// Shows the item as `name` to players using `locale`.
+    pub fn name<L: AsRef<str>, N: AsRef<str>>(mut self, locale: L, name: N) -> Self {
+        self.names.insert(locale.as_ref().to_owned(), name.as_ref().to_owned());
+        self
+    }
+
+    pub fn attribute<N: AsRef<str>, V: Into<AttributeValue>>(mut self, name: N, value: V) -> Self {
+        self.attributes.insert(name.as_ref().to_owned(), value.into());
+        self
+    }
+
+    fn build(self) -> SuffixDef {
         SuffixDef {
             label: self.label,
-            id: self.id.unwrap_or(next_id),
+            id: self.id,
+            aliases: self.aliases,
+            names: self.names,
+            attributes: self.attributes,
         }
     }
 }
 
-#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
 pub struct KindDef {
     pub label: String,
 
-    pub id: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<u32>,
+
+    /// Other labels the kind can be found by.
+    #[serde(default = "Vec::new", skip_serializing_if = "Vec::is_empty")]
+    pub aliases: Vec<String>,
+
+    /// Names to show players by locale; see `SuffixDef::names`.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub names: BTreeMap<String, String>,
+
+    /// Attributes of every piece of the kind, such as `points: 10`.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub attributes: BTreeMap<String, AttributeValue>,
 
     #[serde(
         default = "default_suffix_range",
@@ -62,13 +196,20 @@ pub struct KindDef {
 
     #[serde(default = "Vec::new", skip_serializing_if = "Vec::is_empty")]
     pub suffixes: Vec<SuffixDef>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub suffix_product: Option<SuffixProductDef>,
 }
 
 pub struct KindDefBuilder {
     label: String,
     id: Option<u32>,
+    aliases: Vec<String>,
+    names: BTreeMap<String, String>,
+    attributes: BTreeMap<String, AttributeValue>,
     suffix_range: Option<SuffixRangeDef>,
     suffixes: Vec<SuffixDef>,
+    suffix_product: Option<SuffixProductDef>,
 }
 
 impl KindDef {
@@ -76,43 +217,86 @@ impl KindDef {
         KindDefBuilder {
             label: label.as_ref().to_owned(),
             id: None,
+            aliases: Vec::new(),
+            names: BTreeMap::new(),
+            attributes: BTreeMap::new(),
             suffix_range: None,
             suffixes: Vec::new(),
+            suffix_product: None,
         }
     }
 }
 
 impl KindDefBuilder {
+    #[allow(clippy::unnecessary_fallible_conversions)]
     pub fn id(mut self, id: u32) -> Self {
         self.id = id.try_into().unwrap();
         self
     }
 
+    pub fn alias<T: AsRef<str>>(mut self, alias: T) -> Self {
+        self.aliases.push(alias.as_ref().to_owned());
+        self
+    }
+
+    /// Shows the item as `name` to players using `locale`.
+    pub fn name<L: AsRef<str>, N: AsRef<str>>(mut self, locale: L, name: N) -> Self {
+        self.names.insert(locale.as_ref().to_owned(), name.as_ref().to_owned());
+        self
+    }
+
+    pub fn attribute<N: AsRef<str>, V: Into<AttributeValue>>(mut self, name: N, value: V) -> Self {
+        self.attributes.insert(name.as_ref().to_owned(), value.into());
+        self
+    }
+
     pub fn suffix_range(mut self, min: i32, max: i32) -> Self {
         self.suffix_range = Some(SuffixRangeDef { min, max });
         self
     }
 
     pub fn suffix(mut self, bld: SuffixDefBuilder) -> Self {
-        let next_id = (self.suffixes.len() + 1) as u32;
-        self.suffixes.push(bld.build(next_id));
+        self.suffixes.push(bld.build());
         self
     }
 
-    fn build(self, next_id: u32) -> KindDef {
+    /// Adds a dimension to the kind's product of suffixes.
+    pub fn dimension<T: AsRef<str>>(mut self, label: T, values: &[&str]) -> Self {
+        let product = self.suffix_product.get_or_insert_with(|| SuffixProductDef { dimensions: Vec::new() });
+        product.dimensions.push(DimensionDef {
+            label: label.as_ref().to_owned(),
+            values: values.iter().map(|v| (*v).to_owned()).collect(),
+        });
+        self
+    }
+
+    fn build(self) -> KindDef {
         KindDef {
             label: self.label,
-            id: self.id.unwrap_or(next_id),
+            id: self.id,
+            aliases: self.aliases,
+            names: self.names,
+            attributes: self.attributes,
             suffix_range: self.suffix_range,
             suffixes: self.suffixes,
+            suffix_product: self.suffix_product,
         }
     }
 }
 
-#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
 pub struct PosDef {
     pub label: String,
-    pub id: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<u32>,
+
+    /// Other labels the position can be found by.
+    #[serde(default = "Vec::new", skip_serializing_if = "Vec::is_empty")]
+    pub aliases: Vec<String>,
+
+    /// Names to show players by locale; see `SuffixDef::names`.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub names: BTreeMap<String, String>,
 
     #[serde(
         default = "default_suffix_range",
@@ -136,6 +320,8 @@ pub struct PosDef {
 pub struct PosDefBuilder {
     label: String,
     id: Option<u32>,
+    aliases: Vec<String>,
+    names: BTreeMap<String, String>,
     suffix_range: Option<SuffixRangeDef>,
     suffixes: Vec<SuffixDef>,
     separate: bool,
@@ -148,6 +334,8 @@ impl PosDef {
         PosDefBuilder {
             label: label.as_ref().to_owned(),
             id: None,
+            aliases: Vec::new(),
+            names: BTreeMap::new(),
             suffix_range: None,
             suffixes: Vec::new(),
             separate: false,
@@ -159,19 +347,30 @@ impl PosDef {
 
 impl PosDefBuilder {
 
+    #[allow(clippy::unnecessary_fallible_conversions)]
     pub fn id(mut self, id: u32) -> Self {
         self.id = id.try_into().unwrap();
         self
     }
 
+    pub fn alias<T: AsRef<str>>(mut self, alias: T) -> Self {
+        self.aliases.push(alias.as_ref().to_owned());
+        self
+    }
+
+    /// Shows the item as `name` to players using `locale`.
+    pub fn name<L: AsRef<str>, N: AsRef<str>>(mut self, locale: L, name: N) -> Self {
+        self.names.insert(locale.as_ref().to_owned(), name.as_ref().to_owned());
+        self
+    }
+
     pub fn suffix_range(mut self, min: i32, max: i32) -> Self {
         self.suffix_range = Some(SuffixRangeDef { min, max });
         self
     }
 
     pub fn suffix(mut self, bld: SuffixDefBuilder) -> Self {
-        let next_id = (self.suffixes.len() + 1) as u32;
-        self.suffixes.push(bld.build(next_id));
+        self.suffixes.push(bld.build());
         self
     }
 
@@ -190,10 +389,12 @@ impl PosDefBuilder {
         self
     }
 
-    fn build(self, next_id: u32) -> PosDef {
+    fn build(self) -> PosDef {
         PosDef {
             label: self.label,
-            id: self.id.unwrap_or(next_id),
+            id: self.id,
+            aliases: self.aliases,
+            names: self.names,
             suffix_range: self.suffix_range,
             suffixes: self.suffixes,
             separate: self.separate,
@@ -203,13 +404,169 @@ impl PosDefBuilder {
     }
 }
 
-#[derive(Debug, PartialEq, Serialize, Deserialize)]
+/// Moves the suffixes `from` of the old item labelled `label` so the first
+/// becomes `to`, optionally into the new item labelled `into`.
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct SuffixMapDef {
+    pub label: String,
+    pub from: SuffixRangeDef,
+    pub to: i32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub into: Option<String>,
+}
+
+/// One step in upgrading the rows of a game saved under the previous
+/// version of a definition. Renames and suffix maps refer to old labels.
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MigrationDef {
+    RenamePos { from: String, to: String },
+    RenameKind { from: String, to: String },
+    RemapPos { from: u32, to: u32 },
+    RemapKind { from: u32, to: u32 },
+    MapPosSuffixes(SuffixMapDef),
+    MapKindSuffixes(SuffixMapDef),
+}
+
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
 pub struct GameDef {
     pub label: String,
     pub min_players: u32,
     pub max_players: u32,
     pub kind_defs: Vec<KindDef>,
     pub pos_defs: Vec<PosDef>,
+    #[serde(default = "Vec::new", skip_serializing_if = "Vec::is_empty")]
+    pub migrations: Vec<MigrationDef>,
+}
+
+/// How ids are chosen for items that were not given one.
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum IdAllocation {
+    /// The item's place in its list. Inserting an item shifts the ids of
+    /// every unnumbered item after it.
+    Positional,
+    /// One more than the largest id already in the list, so existing ids
+    /// never change. The ids of removed items can be handed out again once
+    /// no larger id is left; use `GameDef::assign_ids_from` with the
+    /// previous version to rule that out.
+    Stable,
+}
+
+/// An item of a `GameDef` list that is numbered by its id.
+trait Numbered {
+    fn label(&self) -> &str;
+    fn id(&self) -> Option<u32>;
+    fn id_mut(&mut self) -> &mut Option<u32>;
+}
+
+impl Numbered for SuffixDef {
+    fn label(&self) -> &str {
+        &self.label
+    }
+    fn id(&self) -> Option<u32> {
+        self.id
+    }
+    fn id_mut(&mut self) -> &mut Option<u32> {
+        &mut self.id
+    }
+}
+
+impl Numbered for KindDef {
+    fn label(&self) -> &str {
+        &self.label
+    }
+    fn id(&self) -> Option<u32> {
+        self.id
+    }
+    fn id_mut(&mut self) -> &mut Option<u32> {
+        &mut self.id
+    }
+}
+
+impl Numbered for PosDef {
+    fn label(&self) -> &str {
+        &self.label
+    }
+    fn id(&self) -> Option<u32> {
+        self.id
+    }
+    fn id_mut(&mut self) -> &mut Option<u32> {
+        &mut self.id
+    }
+}
+
+/// Gives every item whose id was left out an id chosen by `allocation`.
+/// Stable ids start after `floor` as well as the ids in use.
+fn assign_missing_ids<T: Numbered>(items: &mut [T], allocation: IdAllocation, floor: u32) {
+    let mut next = items.iter().filter_map(Numbered::id).fold(floor, u32::max);
+    for (index, item) in items.iter_mut().enumerate() {
+        let id = item.id_mut();
+        if id.is_none() {
+            *id = Some(match allocation {
+                IdAllocation::Positional => (index + 1) as u32,
+                IdAllocation::Stable => {
+                    next += 1;
+                    next
+                }
+            });
+        }
+    }
+}
+
+/// Numbers `items` as an edited version of `previous`: an unnumbered item
+/// takes the id of the previous item with its label, unless that id has been
+/// taken since, and new items are numbered after every id of either version.
+fn assign_ids_from<T: Numbered>(items: &mut [T], previous: &[T]) {
+    let mut used: Vec<u32> = items.iter().filter_map(Numbered::id).collect();
+    for item in items.iter_mut() {
+        if item.id().is_some() {
+            continue;
+        }
+        let old = previous.iter().find(|p| p.label() == item.label());
+        if let Some(old) = old.and_then(Numbered::id) {
+            if !used.contains(&old) {
+                *item.id_mut() = Some(old);
+                used.push(old);
+            }
+        }
+    }
+    let floor = previous.iter().filter_map(Numbered::id).max().unwrap_or(0);
+    assign_missing_ids(items, IdAllocation::Stable, floor);
+}
+
+impl GameDef {
+    /// Fills in missing ids the way `GameDefBuilder` assigns them by default.
+    pub fn assign_missing_ids(&mut self) {
+        self.assign_missing_ids_with(IdAllocation::Positional);
+    }
+
+    pub fn assign_missing_ids_with(&mut self, allocation: IdAllocation) {
+        assign_missing_ids(&mut self.kind_defs, allocation, 0);
+        assign_missing_ids(&mut self.pos_defs, allocation, 0);
+        for def in &mut self.kind_defs {
+            assign_missing_ids(&mut def.suffixes, allocation, 0);
+        }
+        for def in &mut self.pos_defs {
+            assign_missing_ids(&mut def.suffixes, allocation, 0);
+        }
+    }
+
+    /// Fills in missing ids for an edited version of `previous`. Items that
+    /// kept their label keep their old id, and new items are numbered after
+    /// every id used in either version, so the ids of removed items are never
+    /// handed out again and saved games stay readable.
+    pub fn assign_ids_from(&mut self, previous: &GameDef) {
+        assign_ids_from(&mut self.kind_defs, &previous.kind_defs);
+        for def in &mut self.kind_defs {
+            let old = previous.kind_defs.iter().find(|p| p.label == def.label);
+            assign_ids_from(&mut def.suffixes, old.map_or(&[][..], |p| &p.suffixes));
+        }
+        assign_ids_from(&mut self.pos_defs, &previous.pos_defs);
+        for def in &mut self.pos_defs {
+            let old = previous.pos_defs.iter().find(|p| p.label == def.label);
+            assign_ids_from(&mut def.suffixes, old.map_or(&[][..], |p| &p.suffixes));
+        }
+    }
 }
 
 pub struct GameDefBuilder {
@@ -218,6 +575,8 @@ pub struct GameDefBuilder {
     max_players: u32,
     kind_defs: Vec<KindDef>,
     pos_defs: Vec<PosDef>,
+    migrations: Vec<MigrationDef>,
+    id_allocation: IdAllocation,
 }
 
 impl GameDefBuilder {
@@ -228,6 +587,8 @@ impl GameDefBuilder {
             max_players: 2,
             kind_defs: Vec::new(),
             pos_defs: Vec::new(),
+            migrations: Vec::new(),
+            id_allocation: IdAllocation::Positional,
         }
     }
 
@@ -241,26 +602,37 @@ impl GameDefBuilder {
         self
     }
 
+    pub fn id_allocation(mut self, allocation: IdAllocation) -> Self {
+        self.id_allocation = allocation;
+        self
+    }
+
     pub fn kind(mut self, bld: KindDefBuilder) -> Self {
-        let next_id = (self.kind_defs.len() + 1) as u32;
-        self.kind_defs.push(bld.build(next_id));
+        self.kind_defs.push(bld.build());
         self
     }
 
     pub fn pos(mut self, bld: PosDefBuilder) -> Self {
-        let next_id = (self.pos_defs.len() + 1) as u32;
-        self.pos_defs.push(bld.build(next_id));
+        self.pos_defs.push(bld.build());
+        self
+    }
+
+    pub fn migration(mut self, migration: MigrationDef) -> Self {
+        self.migrations.push(migration);
         self
     }
 
     pub fn build(self) -> GameDef {
-        GameDef {
+        let mut def = GameDef {
             label: self.label.to_owned(),
             min_players: self.min_players,
             max_players: self.max_players,
             kind_defs: self.kind_defs,
             pos_defs: self.pos_defs,
-        }
+            migrations: self.migrations,
+        };
+        def.assign_missing_ids_with(self.id_allocation);
+        def
     }
 }
 
@@ -339,4 +711,80 @@ pos_defs:
         let deserialized_point: GameDef = serde_yaml::from_str(&s).unwrap();
         assert_eq!(def, deserialized_point);
     }
+
+    #[test]
+    fn missing_ids_are_assigned_from_list_order() {
+        let mut def: GameDef = serde_yaml::from_str(
+            "
+label: whist
+min_players: 3
+max_players: 5
+kind_defs:
+  - label: card
+  - label: suit
+    suffixes:
+      - label: hearts
+      - label: clubs
+        id: 7
+pos_defs:
+  - label: deck
+    id: 4
+  - label: hand
+",
+        )
+        .unwrap();
+
+        def.assign_missing_ids();
+
+        let expected = GameDefBuilder::bld("whist")
+            .min_players(3)
+            .max_players(5)
+            .kind(KindDef::bld("card"))
+            .kind(
+                KindDef::bld("suit")
+                    .suffix(SuffixDef::bld("hearts"))
+                    .suffix(SuffixDef::bld("clubs").id(7)),
+            )
+            .pos(PosDef::bld("deck").id(4))
+            .pos(PosDef::bld("hand"))
+            .build();
+        assert_eq!(expected, def);
+    }
+
+    #[test]
+    fn suffix_id_zero_is_kept() {
+        let mut def: GameDef = serde_yaml::from_str(
+            "
+label: dice
+min_players: 1
+max_players: 1
+kind_defs:
+  - label: die
+    suffixes:
+      - label: blank
+        id: 0
+      - label: one
+pos_defs: []
+",
+        )
+        .unwrap();
+
+        def.assign_missing_ids_with(IdAllocation::Stable);
+
+        let ids: Vec<u32> = def.kind_defs[0].suffixes.iter().map(|d| d.id.unwrap()).collect();
+        assert_eq!(vec![0, 1], ids);
+    }
+
+    #[test]
+    fn stable_ids_never_shift() {
+        let def = GameDefBuilder::bld("whist")
+            .id_allocation(IdAllocation::Stable)
+            .kind(KindDef::bld("card").id(4))
+            .kind(KindDef::bld("joker"))
+            .kind(KindDef::bld("suit").id(2))
+            .build();
+
+        let ids: Vec<u32> = def.kind_defs.iter().map(|d| d.id.unwrap()).collect();
+        assert_eq!(vec![4, 5, 2], ids);
+    }
 }
@@ -0,0 +1,180 @@
+//! Renders the structure of a game, its positions and kinds of piece, as
+//! Graphviz DOT or Mermaid text for design documents and reviews.
+//!
+//! There are no edges yet: a `GameSpec` does not say which moves are legal,
+//! so there are no flows between positions to draw.
+
+use crate::specs::{GameSpec, KindSpec, PosSpec, SuffixSpec};
+
+/// The lines describing an item: its label, id, flags and suffixes.
+struct Node {
+    id: String,
+    lines: Vec<String>,
+    hidden: bool,
+}
+
+fn describe_suffixes(suffixes: &SuffixSpec) -> Option<String> {
+    match suffixes {
+        SuffixSpec::Empty => None,
+        SuffixSpec::Range(range) => Some(format!("suffixes {}..{}", range.min.0, range.max.0)),
+        SuffixSpec::Table(table) => {
+            let labels: Vec<&str> = table.iter().map(|r| r.label.as_str()).collect();
+            Some(format!("suffixes {}", labels.join(", ")))
+        }
+        SuffixSpec::Product(product) => {
+            let labels: Vec<&str> = product.dimensions().iter().map(|d| d.label.as_str()).collect();
+            Some(format!("suffixes {}", labels.join(" x ")))
+        }
+    }
+}
+
+fn pos_node(spec: &PosSpec) -> Node {
+    let mut lines = vec![spec.label.clone(), format!("id {}", spec.id.as_u32())];
+    let flags: Vec<&str> = [
+        (spec.hidden, "hidden"),
+        (spec.ordered, "ordered"),
+        (spec.separate, "separate"),
+    ]
+        .iter()
+        .filter(|(set, _)| *set)
+        .map(|(_, flag)| *flag)
+        .collect();
+    if !flags.is_empty() {
+        lines.push(flags.join(", "));
+    }
+    lines.extend(describe_suffixes(&spec.suffixes));
+    Node { id: format!("pos_{}", spec.id.as_u32()), lines, hidden: spec.hidden }
+}
+
+fn kind_node(spec: &KindSpec) -> Node {
+    let mut lines = vec![spec.label.clone(), format!("id {}", u32::from(spec.id))];
+    lines.extend(describe_suffixes(&spec.suffixes));
+    Node { id: format!("kind_{}", u32::from(spec.id)), lines, hidden: false }
+}
+
+fn nodes(spec: &GameSpec) -> (Vec<Node>, Vec<Node>) {
+    (spec.positions().map(pos_node).collect(), spec.kinds().map(kind_node).collect())
+}
+
+fn dot_escape(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+fn mermaid_escape(text: &str) -> String {
+    text.replace('"', "#quot;")
+}
+
+impl GameSpec {
+    /// The positions and kinds of the game as a Graphviz digraph, one
+    /// cluster each. Hidden positions are drawn dashed.
+    pub fn to_dot(&self) -> String {
+        let (positions, kinds) = nodes(self);
+        let mut out = format!("digraph \"{}\" {{\n", dot_escape(self.label()));
+        out.push_str("    rankdir=LR;\n");
+        for (name, shape, nodes) in [("positions", "box", positions), ("kinds", "ellipse", kinds)] {
+            out.push_str(&format!("    subgraph cluster_{} {{\n", name));
+            out.push_str(&format!("        label=\"{}\";\n", name));
+            out.push_str(&format!("        node [shape={}];\n", shape));
+            for node in nodes {
+                let label: Vec<String> = node.lines.iter().map(|l| dot_escape(l)).collect();
+                let style = if node.hidden { ", style=dashed" } else { "" };
+                out.push_str(&format!(
+                    "        {} [label=\"{}\"{}];\n",
+                    node.id,
+                    label.join("\\n"),
+                    style
+                ));
+            }
+            out.push_str("    }\n");
+        }
+        out.push_str("}\n");
+        out
+    }
+
+    /// The positions and kinds of the game as a Mermaid flowchart, one
+    /// subgraph each. Hidden positions get the `hidden` class.
+    pub fn to_mermaid(&self) -> String {
+        let (positions, kinds) = nodes(self);
+        let mut out = String::from("flowchart LR\n");
+        for (name, open, close, nodes) in [("positions", "[", "]", positions), ("kinds", "([", "])", kinds)] {
+            out.push_str(&format!("    subgraph {}\n", name));
+            for node in nodes {
+                let label: Vec<String> = node.lines.iter().map(|l| mermaid_escape(l)).collect();
+                out.push_str(&format!("        {}{}\"{}\"{}", node.id, open, label.join("<br/>"), close));
+                if node.hidden {
+                    out.push_str(":::hidden");
+                }
+                out.push('\n');
+            }
+            out.push_str("    end\n");
+        }
+        out.push_str("    classDef hidden stroke-dasharray: 5 5\n");
+        out
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::defs::{GameDefBuilder, KindDef, PosDef, SuffixDef};
+    use std::convert::TryInto;
+
+    fn create_spec() -> GameSpec {
+        GameDefBuilder::bld("whist")
+            .kind(KindDef::bld("card").suffix_range(1, 52))
+            .kind(
+                KindDef::bld("suit")
+                    .suffix(SuffixDef::bld("hearts"))
+                    .suffix(SuffixDef::bld("clubs")),
+            )
+            .pos(PosDef::bld("deck").hidden().ordered())
+            .pos(PosDef::bld("hand").hidden().separate())
+            .pos(PosDef::bld("trick"))
+            .build()
+            .try_into()
+            .unwrap()
+    }
+
+    #[test]
+    fn can_render_dot() {
+        assert_eq!(
+            "digraph \"whist\" {
+    rankdir=LR;
+    subgraph cluster_positions {
+        label=\"positions\";
+        node [shape=box];
+        pos_1 [label=\"deck\\nid 1\\nhidden, ordered\", style=dashed];
+        pos_2 [label=\"hand\\nid 2\\nhidden, separate\", style=dashed];
+        pos_3 [label=\"trick\\nid 3\"];
+    }
+    subgraph cluster_kinds {
+        label=\"kinds\";
+        node [shape=ellipse];
+        kind_1 [label=\"card\\nid 1\\nsuffixes 1..52\"];
+        kind_2 [label=\"suit\\nid 2\\nsuffixes hearts, clubs\"];
+    }
+}
+",
+            create_spec().to_dot()
+        );
+    }
+
+    #[test]
+    fn can_render_mermaid() {
+        assert_eq!(
+            "flowchart LR
+    subgraph positions
+        pos_1[\"deck<br/>id 1<br/>hidden, ordered\"]:::hidden
+        pos_2[\"hand<br/>id 2<br/>hidden, separate\"]:::hidden
+        pos_3[\"trick<br/>id 3\"]
+    end
+    subgraph kinds
+        kind_1([\"card<br/>id 1<br/>suffixes 1..52\"])
+        kind_2([\"suit<br/>id 2<br/>suffixes hearts, clubs\"])
+    end
+    classDef hidden stroke-dasharray: 5 5
+",
+            create_spec().to_mermaid()
+        );
+    }
+}
@@ -0,0 +1,329 @@
+use std::collections::btree_map;
+use std::collections::BTreeMap;
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use crate::coords::{QKind, QPos};
+use crate::specs::{GameSpec, PlayerNum};
+use crate::state::ExportRow;
+
+/// A row whose count went from `from` to `row.count`.
+#[derive(Debug, Eq, PartialEq, Copy, Clone, Serialize, Deserialize)]
+pub struct CountChange {
+    pub row: ExportRow,
+    pub from: u32,
+}
+
+/// An ordered slot whose piece went from `from` to `row.kind`.
+#[derive(Debug, Eq, PartialEq, Copy, Clone, Serialize, Deserialize)]
+pub struct SlotChange {
+    pub row: ExportRow,
+    pub from: QKind,
+}
+
+#[derive(Debug, Default, Eq, PartialEq, Clone, Serialize, Deserialize)]
+pub struct PosDiff {
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub added: Vec<ExportRow>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub removed: Vec<ExportRow>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub changed: Vec<CountChange>,
+    /// Ordered slots that now hold a different piece, as when pieces are
+    /// reordered.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub reordered: Vec<SlotChange>,
+}
+
+impl PosDiff {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty() && self.reordered.is_empty()
+    }
+}
+
+/// The rows that were added, removed or changed, grouped by position.
+#[derive(Default, Eq, PartialEq, Clone, Serialize, Deserialize)]
+#[serde(from = "Vec<PosEntry>", into = "Vec<PosEntry>")]
+pub struct Diff {
+    changes: BTreeMap<QPos, PosDiff>,
+}
+
+/// How a diff is serialized, since positions can not be map keys in JSON.
+#[derive(Serialize, Deserialize)]
+struct PosEntry {
+    pos: QPos,
+    #[serde(flatten)]
+    diff: PosDiff,
+}
+
+impl From<Diff> for Vec<PosEntry> {
+    fn from(diff: Diff) -> Self {
+        diff.changes
+            .into_iter()
+            .map(|(pos, diff)| PosEntry { pos, diff })
+            .collect()
+    }
+}
+
+impl From<Vec<PosEntry>> for Diff {
+    fn from(entries: Vec<PosEntry>) -> Self {
+        let changes = entries.into_iter()
+            .map(|entry| (entry.pos, entry.diff))
+            .collect();
+        Diff { changes }
+    }
+}
+
+pub trait Subscriber {
+    fn notify(&mut self, diff: &Diff);
+}
+
+impl<F> Subscriber for F
+    where
+        F: FnMut(&Diff),
+{
+    fn notify(&mut self, diff: &Diff) {
+        self(diff)
+    }
+}
+
+fn index_rows(rows: Vec<ExportRow>) -> BTreeMap<(QPos, QKind), ExportRow> {
+    rows.into_iter()
+        .map(|row| ((row.pos, row.kind), row))
+        .collect()
+}
+
+impl Diff {
+    pub fn new() -> Diff {
+        Diff {
+            changes: BTreeMap::new(),
+        }
+    }
+
+    pub fn between(before: Vec<ExportRow>, after: Vec<ExportRow>) -> Diff {
+        let mut diff = Diff::new();
+        diff.extend_between(before, after);
+        diff
+    }
+
+    pub(crate) fn extend_between(&mut self, before: Vec<ExportRow>, after: Vec<ExportRow>) {
+        let before = index_rows(before);
+        let mut after = index_rows(after);
+
+        for (key, old) in before {
+            match after.remove(&key) {
+                Some(new) if new.count != old.count => {
+                    self.entry(key.0).changed.push(CountChange { row: new, from: old.count })
+                }
+                Some(_) => {}
+                None => self.entry(key.0).removed.push(old),
+            }
+        }
+
+        for (key, new) in after {
+            self.entry(key.0).added.push(new);
+        }
+    }
+
+    /// Like `extend_between`, for the slots of ordered positions, which hold
+    /// one piece each: a slot whose piece changed is reported as reordered
+    /// rather than as a removed and an added row.
+    pub(crate) fn extend_between_slots(&mut self, before: Vec<ExportRow>, after: Vec<ExportRow>) {
+        let before: BTreeMap<QPos, ExportRow> = before.into_iter().map(|row| (row.pos, row)).collect();
+        let mut after: BTreeMap<QPos, ExportRow> = after.into_iter().map(|row| (row.pos, row)).collect();
+
+        for (pos, old) in before {
+            match after.remove(&pos) {
+                Some(new) if new.kind != old.kind => {
+                    self.entry(pos).reordered.push(SlotChange { row: new, from: old.kind })
+                }
+                Some(_) => {}
+                None => self.entry(pos).removed.push(old),
+            }
+        }
+
+        for (pos, new) in after {
+            self.entry(pos).added.push(new);
+        }
+    }
+
+    fn entry(&mut self, pos: QPos) -> &mut PosDiff {
+        self.changes.entry(pos).or_default()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+
+    pub fn get(&self, pos: &QPos) -> Option<&PosDiff> {
+        self.changes.get(pos)
+    }
+
+    pub fn iter(&self) -> btree_map::Iter<'_, QPos, PosDiff> {
+        self.changes.iter()
+    }
+
+    /// The diff that undoes this one.
+    pub fn inverse(&self) -> Diff {
+        let changes = self.changes
+            .iter()
+            .map(|(pos, diff)| {
+                let inverse = PosDiff {
+                    added: diff.removed.clone(),
+                    removed: diff.added.clone(),
+                    changed: diff.changed
+                        .iter()
+                        .map(|c| CountChange { row: ExportRow { count: c.from, ..c.row }, from: c.row.count })
+                        .collect(),
+                    reordered: diff.reordered
+                        .iter()
+                        .map(|c| SlotChange { row: ExportRow { kind: c.from, ..c.row }, from: c.row.kind })
+                        .collect(),
+                };
+                (*pos, inverse)
+            })
+            .collect();
+        Diff { changes }
+    }
+
+    /// The part of this diff that `player` is allowed to see.
+    pub fn for_player(&self, spec: &GameSpec, player: PlayerNum) -> Diff {
+        let changes = self.changes
+            .iter()
+            .filter(|(pos, _)| spec.is_visible(pos, player))
+            .map(|(pos, diff)| (*pos, diff.clone()))
+            .collect();
+        Diff { changes }
+    }
+}
+
+impl<'a> IntoIterator for &'a Diff {
+    type Item = (&'a QPos, &'a PosDiff);
+    type IntoIter = btree_map::Iter<'a, QPos, PosDiff>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl fmt::Debug for Diff {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for diff in self.changes.values() {
+            for row in &diff.added {
+                write!(f, "\n+ {:?}", row)?;
+            }
+            for row in &diff.removed {
+                write!(f, "\n- {:?}", row)?;
+            }
+            for change in &diff.changed {
+                write!(f, "\n~ {:?} (was x{})", change.row, change.from)?;
+            }
+            for change in &diff.reordered {
+                write!(f, "\n~ {:?} (was {})", change.row, change.from)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::coords::{Region, Suffix};
+    use crate::defs::{GameDefBuilder, KindDef, PosDef};
+    use std::convert::TryInto;
+
+    fn row(spec: &GameSpec, pos: &str, region: u16, count: u32) -> ExportRow {
+        let pos = spec.pos_specs.find_by_label(pos).unwrap().id;
+        let kind = spec.kind_specs.find_by_label("card").unwrap().id;
+        ExportRow {
+            pos: QPos { pos, region: Region(region), suffix: Suffix(0) },
+            kind: QKind { kind, suffix: Suffix(1) },
+            count,
+        }
+    }
+
+    #[test]
+    fn can_diff_rows() {
+        let spec: GameSpec = GameDefBuilder::bld("whist")
+            .kind(KindDef::bld("card").suffix_range(1, 52))
+            .pos(PosDef::bld("deck"))
+            .pos(PosDef::bld("trick"))
+            .pos(PosDef::bld("trump"))
+            .build()
+            .try_into()
+            .unwrap();
+
+        let diff = Diff::between(
+            vec![row(&spec, "deck", 0, 2), row(&spec, "trick", 0, 1)],
+            vec![row(&spec, "deck", 0, 1), row(&spec, "trump", 0, 1)],
+        );
+
+        let deck = diff.get(&row(&spec, "deck", 0, 0).pos).unwrap();
+        assert_eq!(vec![CountChange { row: row(&spec, "deck", 0, 1), from: 2 }], deck.changed);
+
+        let trick = diff.get(&row(&spec, "trick", 0, 0).pos).unwrap();
+        assert_eq!(vec![row(&spec, "trick", 0, 1)], trick.removed);
+
+        let trump = diff.get(&row(&spec, "trump", 0, 0).pos).unwrap();
+        assert_eq!(vec![row(&spec, "trump", 0, 1)], trump.added);
+    }
+
+    #[test]
+    fn can_round_trip_diff_through_json() {
+        let spec: GameSpec = GameDefBuilder::bld("whist")
+            .kind(KindDef::bld("card").suffix_range(1, 52))
+            .pos(PosDef::bld("deck"))
+            .pos(PosDef::bld("trick"))
+            .build()
+            .try_into()
+            .unwrap();
+
+        let diff = Diff::between(
+            vec![row(&spec, "deck", 0, 2), row(&spec, "trick", 1, 1)],
+            vec![row(&spec, "deck", 0, 1)],
+        );
+
+        let json = serde_json::to_string(&diff).unwrap();
+        assert_eq!(
+            r#"[{"pos":{"pos":1,"region":0,"suffix":0},"changed":[{"row":{"pos":{"pos":1,"region":0,"suffix":0},"kind":{"kind":1,"suffix":1},"count":1},"from":2}]},{"pos":{"pos":2,"region":1,"suffix":0},"removed":[{"pos":{"pos":2,"region":1,"suffix":0},"kind":{"kind":1,"suffix":1},"count":1}]}]"#,
+            json
+        );
+        assert_eq!(diff, serde_json::from_str(&json).unwrap());
+    }
+
+    #[test]
+    fn can_filter_diff_for_player() {
+        let spec: GameSpec = GameDefBuilder::bld("whist")
+            .kind(KindDef::bld("card").suffix_range(1, 52))
+            .pos(PosDef::bld("deck").hidden())
+            .pos(PosDef::bld("hand").hidden().separate())
+            .pos(PosDef::bld("trick").separate())
+            .build()
+            .try_into()
+            .unwrap();
+
+        let diff = Diff::between(
+            vec![],
+            vec![
+                row(&spec, "deck", 0, 1),
+                row(&spec, "hand", 0, 1),
+                row(&spec, "hand", 1, 1),
+                row(&spec, "trick", 0, 1),
+                row(&spec, "trick", 1, 1),
+            ],
+        );
+
+        let expected = Diff::between(
+            vec![],
+            vec![
+                row(&spec, "hand", 1, 1),
+                row(&spec, "trick", 0, 1),
+                row(&spec, "trick", 1, 1),
+            ],
+        );
+
+        assert_eq!(expected, diff.for_player(&spec, PlayerNum(1)));
+    }
+}
@@ -1,18 +1,30 @@
 use crate::coords::{Kind, Pos, Suffix};
+use crate::defs::SuffixMapDef;
 use crate::lookup::Collision;
 
 #[derive(Debug, PartialEq)]
 pub enum SuffixRowError {
     Thing,
+    /// The suffix was not given an id.
+    MissingId,
 }
 
 #[derive(Debug, PartialEq)]
 pub enum ItemError {
+    /// The item was not given an id.
+    MissingId,
     InvalidId(u32),
     SuffixesAndRangeDefined,
     InvalidSuffixRange(i32, i32),
     InvalidSuffixRow(SuffixRowError),
     InvalidSuffixTable(Collision<Suffix>),
+    /// A suffix_product was given along with a suffix_range or suffixes.
+    SuffixProductAndSuffixesDefined,
+    /// The suffix_product has no dimensions, a dimension without values, or
+    /// more than `MAX_SUFFIX_PRODUCT` suffixes.
+    InvalidSuffixProduct,
+    /// The suffix_product has more than one dimension with this label.
+    DuplicateDimension(String),
 }
 
 #[derive(Debug, PartialEq)]
@@ -22,4 +34,7 @@ pub enum Error {
     InvalidPosTable(Collision<Pos>),
     InvalidKind(ItemError),
     InvalidKindTable(Collision<Kind>),
+    /// A migration maps an empty range of suffixes, or maps them past the
+    /// largest suffix.
+    InvalidSuffixMap(SuffixMapDef),
 }
@@ -1,6 +1,19 @@
+pub mod compat;
+pub mod console;
 pub mod coords;
 pub mod defs;
+pub mod diagram;
+pub mod diff;
 pub mod error;
+pub mod lint;
 pub mod lookup;
+pub mod mcts;
+pub mod migrate;
+pub mod render;
+pub mod schema;
+pub mod server;
 pub mod specs;
 pub mod state;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
+pub mod view;
@@ -0,0 +1,660 @@
+//! Checks a `GameDef` YAML file for every problem that would stop it
+//! becoming a `GameSpec`, and rewrites files in a canonical form.
+
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::fmt;
+
+use crate::defs::{GameDef, IdAllocation, MigrationDef};
+use crate::error::{ItemError, SuffixRowError};
+use crate::lookup::{closest_label, Collision};
+use crate::specs::{KindSpec, PosSpec, MAX_SUFFIX_PRODUCT};
+
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    /// The 1 based line the problem was found on, when it can be located.
+    pub line: Option<usize>,
+    pub message: String,
+    pub suggestion: Option<String>,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(line) = self.line {
+            write!(f, "{}: ", line)?;
+        }
+        match self.severity {
+            Severity::Error => write!(f, "error: {}", self.message)?,
+            Severity::Warning => write!(f, "warning: {}", self.message)?,
+        }
+        if let Some(suggestion) = &self.suggestion {
+            write!(f, "\n  help: {}", suggestion)?;
+        }
+        Ok(())
+    }
+}
+
+fn indent(line: &str) -> usize {
+    line.len() - line.trim_start().len()
+}
+
+fn is_blank(line: &str) -> bool {
+    let line = line.trim();
+    line.is_empty() || line.starts_with('#')
+}
+
+/// Finds the lines of the items of the block list under `key`, searching
+/// from `start` up to `end`. The list's own line is returned first.
+fn list_items(lines: &[&str], start: usize, end: usize, key: &str) -> Option<(usize, Vec<usize>)> {
+    let key_line = (start..end).find(|i| {
+        let trimmed = lines[*i].trim_start().trim_start_matches("- ");
+        trimmed.starts_with(key) && trimmed[key.len()..].starts_with(':')
+    })?;
+    let key_indent = indent(lines[key_line]);
+
+    let mut items = Vec::new();
+    let mut item_indent = None;
+    for (i, line) in lines.iter().enumerate().take(end).skip(key_line + 1) {
+        if is_blank(line) {
+            continue;
+        }
+        let is_item = line.trim_start().starts_with('-');
+        match item_indent {
+            None if is_item && indent(line) >= key_indent => item_indent = Some(indent(line)),
+            None => break,
+            Some(n) if indent(line) < n || (indent(line) == n && !is_item) => break,
+            Some(_) => {}
+        }
+        if is_item && Some(indent(line)) == item_indent {
+            items.push(i);
+        }
+    }
+    Some((key_line, items))
+}
+
+/// Where each part of a game definition is in its source.
+struct Locator<'a> {
+    lines: Vec<&'a str>,
+}
+
+impl<'a> Locator<'a> {
+    fn new(source: &'a str) -> Locator<'a> {
+        Locator {
+            lines: source.lines().collect(),
+        }
+    }
+
+    fn top_level(&self, key: &str) -> Option<usize> {
+        self.lines.iter()
+            .position(|l| indent(l) == 0 && l.starts_with(key) && l[key.len()..].starts_with(':'))
+            .map(|i| i + 1)
+    }
+
+    fn item(&self, key: &str, index: usize) -> Option<usize> {
+        let (key_line, items) = list_items(&self.lines, 0, self.lines.len(), key)?;
+        Some(items.get(index).copied().unwrap_or(key_line) + 1)
+    }
+}
+
+struct Linter<'a> {
+    def: &'a GameDef,
+    /// The def with the ids `knott fmt` would fill in, so hints never
+    /// suggest an id formatting hands to another item.
+    formatted: GameDef,
+    locator: Locator<'a>,
+    diagnostics: Vec<Diagnostic>,
+}
+
+fn describe_item_error(noun: &str, label: &str, error: &ItemError) -> (String, Option<String>) {
+    match error {
+        ItemError::MissingId => (
+            format!("{} '{}' has no id", noun, label),
+            Some("run `knott fmt` to assign one".to_owned()),
+        ),
+        ItemError::InvalidId(id) => (
+            format!("{} '{}' has id {}, ids must be between 1 and 9999", noun, label, id),
+            None,
+        ),
+        ItemError::SuffixesAndRangeDefined => (
+            format!("{} '{}' has both a suffix_range and suffixes", noun, label),
+            Some("remove one of them".to_owned()),
+        ),
+        ItemError::SuffixProductAndSuffixesDefined => (
+            format!("{} '{}' has a suffix_product as well as a suffix_range or suffixes", noun, label),
+            Some("remove all but one of them".to_owned()),
+        ),
+        ItemError::InvalidSuffixProduct => (
+            format!("{} '{}' has a suffix_product with no suffixes, or more than {}", noun, label, MAX_SUFFIX_PRODUCT),
+            Some("give it at least one dimension, and each dimension at least one value".to_owned()),
+        ),
+        ItemError::DuplicateDimension(dimension) => (
+            format!("{} '{}' has more than one dimension labelled '{}'", noun, label, dimension),
+            Some("rename or remove the duplicate".to_owned()),
+        ),
+        ItemError::InvalidSuffixRange(min, max) => (
+            format!("{} '{}' has an empty suffix_range {}..{}", noun, label, min, max),
+            Some("min must be less than max".to_owned()),
+        ),
+        ItemError::InvalidSuffixRow(SuffixRowError::MissingId) => (
+            format!("{} '{}' has a suffix with no id", noun, label),
+            Some("run `knott fmt` to assign one".to_owned()),
+        ),
+        ItemError::InvalidSuffixRow(e) => (
+            format!("{} '{}' has an invalid suffix: {:?}", noun, label, e),
+            None,
+        ),
+        ItemError::InvalidSuffixTable(Collision::IdCollision(suffix)) => (
+            format!("{} '{}' has more than one suffix with id {}", noun, label, suffix.0),
+            Some("give each suffix its own id".to_owned()),
+        ),
+        ItemError::InvalidSuffixTable(Collision::LabelCollision(suffix)) => (
+            format!("{} '{}' has more than one suffix labelled '{}'", noun, label, suffix),
+            Some("rename or remove the duplicate".to_owned()),
+        ),
+    }
+}
+
+/// An item of a table to check: its label, aliases, id and whether it
+/// converts on its own.
+type TableItem<'a> = (&'a str, &'a [String], Option<u32>, Result<(), ItemError>);
+
+impl<'a> Linter<'a> {
+    fn push(&mut self, severity: Severity, line: Option<usize>, message: String, suggestion: Option<String>) {
+        self.diagnostics.push(Diagnostic { severity, line, message, suggestion });
+    }
+
+    fn check_players(&mut self) {
+        for (key, num) in [("min_players", self.def.min_players), ("max_players", self.def.max_players)] {
+            if num > u8::MAX as u32 {
+                let line = self.locator.top_level(key);
+                self.push(Severity::Error, line, format!("{} is {}, at most 255 are allowed", key, num), None);
+            }
+        }
+        if self.def.min_players > self.def.max_players {
+            let line = self.locator.top_level("min_players");
+            self.push(
+                Severity::Error,
+                line,
+                format!(
+                    "min_players ({}) is greater than max_players ({})",
+                    self.def.min_players, self.def.max_players
+                ),
+                Some("swap them".to_owned()),
+            );
+        }
+    }
+
+    /// Checks the items of one table: each on its own, then ids, labels and aliases across the table.
+    fn check_table(&mut self, key: &str, noun: &str, items: Vec<TableItem>, mut next_free: u32) {
+        let mut ids: HashMap<u32, &str> = HashMap::new();
+        let mut labels: HashMap<&str, usize> = HashMap::new();
+
+        for (index, (label, aliases, id, result)) in items.iter().enumerate() {
+            let line = self.locator.item(key, index);
+            if let Err(e) = result {
+                let (message, suggestion) = describe_item_error(noun, label, e);
+                self.push(Severity::Error, line, message, suggestion);
+            }
+            if let Some(id) = *id {
+                if let Some(first) = ids.insert(id, label) {
+                    self.push(
+                        Severity::Error,
+                        line,
+                        format!("{} '{}' reuses id {} of {} '{}'", noun, label, id, noun, first),
+                        Some(format!("use id {}", next_free)),
+                    );
+                    next_free += 1;
+                    ids.insert(id, first);
+                }
+            }
+            if let Some(first) = labels.insert(label, index) {
+                let message = if items[first].0 == *label {
+                    format!("{} '{}' is defined more than once", noun, label)
+                } else {
+                    format!("{} '{}' is already an alias of {} '{}'", noun, label, noun, items[first].0)
+                };
+                self.push(
+                    Severity::Error,
+                    line,
+                    message,
+                    self.locator.item(key, first).map(|l| format!("rename it or remove one; it is first defined on line {}", l)),
+                );
+                labels.insert(label, first);
+            }
+            for alias in aliases.iter() {
+                if let Some(first) = labels.insert(alias, index) {
+                    self.push(
+                        Severity::Error,
+                        line,
+                        format!("{} '{}' has alias '{}', which is already used by {} '{}'", noun, label, alias, noun, items[first].0),
+                        Some("remove the alias".to_owned()),
+                    );
+                    labels.insert(alias, first);
+                }
+            }
+        }
+    }
+
+    fn check_kinds(&mut self) {
+        let items = self.def.kind_defs
+            .iter()
+            .map(|def| (def.label.as_str(), def.aliases.as_slice(), def.id, KindSpec::try_from(def.clone()).map(|_| ())))
+            .collect();
+        let next_free = self.formatted.kind_defs.iter().filter_map(|d| d.id).max().unwrap_or(0) + 1;
+        self.check_table("kind_defs", "kind", items, next_free);
+    }
+
+    fn check_positions(&mut self) {
+        let items = self.def.pos_defs
+            .iter()
+            .map(|def| (def.label.as_str(), def.aliases.as_slice(), def.id, PosSpec::try_from(def.clone()).map(|_| ())))
+            .collect();
+        let next_free = self.formatted.pos_defs.iter().filter_map(|d| d.id).max().unwrap_or(0) + 1;
+        self.check_table("pos_defs", "position", items, next_free);
+    }
+
+    fn check_usage(&mut self) {
+        if self.def.pos_defs.is_empty() {
+            for (index, def) in self.def.kind_defs.iter().enumerate() {
+                let line = self.locator.item("kind_defs", index);
+                self.push(
+                    Severity::Warning,
+                    line,
+                    format!("kind '{}' is unused, the game has no positions to put it in", def.label),
+                    Some("add a position to pos_defs".to_owned()),
+                );
+            }
+        }
+        if self.def.kind_defs.is_empty() {
+            for (index, def) in self.def.pos_defs.iter().enumerate() {
+                let line = self.locator.item("pos_defs", index);
+                self.push(
+                    Severity::Warning,
+                    line,
+                    format!("position '{}' is unused, the game has no kinds to put in it", def.label),
+                    Some("add a kind to kind_defs".to_owned()),
+                );
+            }
+        }
+        if self.def.max_players <= 1 {
+            for (index, def) in self.def.pos_defs.iter().enumerate().filter(|(_, d)| d.separate) {
+                let line = self.locator.item("pos_defs", index);
+                self.push(
+                    Severity::Warning,
+                    line,
+                    format!("position '{}' is separate but only one player can play", def.label),
+                    Some("remove `separate: true`".to_owned()),
+                );
+            }
+        }
+    }
+
+    /// Checks the labels migrations move rows to are defined, and that
+    /// suffix maps move a range of suffixes to one that fits.
+    fn check_migrations(&mut self) {
+        let kinds: Vec<&str> = self.def.kind_defs
+            .iter()
+            .flat_map(|def| std::iter::once(def.label.as_str()).chain(def.aliases.iter().map(String::as_str)))
+            .collect();
+        let positions: Vec<&str> = self.def.pos_defs
+            .iter()
+            .flat_map(|def| std::iter::once(def.label.as_str()).chain(def.aliases.iter().map(String::as_str)))
+            .collect();
+
+        for (index, migration) in self.def.migrations.iter().enumerate() {
+            if let MigrationDef::MapPosSuffixes(map) | MigrationDef::MapKindSuffixes(map) = migration {
+                if map.from.min > map.from.max {
+                    let line = self.locator.item("migrations", index);
+                    let message = format!("migration maps the empty suffix range {}..{}", map.from.min, map.from.max);
+                    self.push(Severity::Error, line, message, Some("min must not be more than max".to_owned()));
+                } else if map.to as i64 + (map.from.max as i64 - map.from.min as i64) > i32::MAX as i64 {
+                    let line = self.locator.item("migrations", index);
+                    let message = format!("migration maps suffixes past {}", i32::MAX);
+                    self.push(Severity::Error, line, message, Some("choose a smaller `to`".to_owned()));
+                }
+            }
+            let (noun, target, labels) = match migration {
+                MigrationDef::RenamePos { to, .. } => ("position", Some(to), &positions),
+                MigrationDef::RenameKind { to, .. } => ("kind", Some(to), &kinds),
+                MigrationDef::MapPosSuffixes(map) => ("position", map.into.as_ref(), &positions),
+                MigrationDef::MapKindSuffixes(map) => ("kind", map.into.as_ref(), &kinds),
+                MigrationDef::RemapPos { .. } | MigrationDef::RemapKind { .. } => continue,
+            };
+            if let Some(target) = target.filter(|t| !labels.contains(&t.as_str())) {
+                let line = self.locator.item("migrations", index);
+                self.push(
+                    Severity::Error,
+                    line,
+                    format!("migration moves rows to {} '{}', which is not defined", noun, target),
+                    closest_label(target, labels.iter().copied()).map(|l| format!("did you mean '{}'?", l)),
+                );
+            }
+        }
+    }
+}
+
+fn parse(source: &str) -> Result<GameDef, Diagnostic> {
+    serde_yaml::from_str(source).map_err(|e| Diagnostic {
+        severity: Severity::Error,
+        line: e.location().map(|l| l.line()),
+        message: e.to_string(),
+        suggestion: None,
+    })
+}
+
+/// Every problem with the game definition in `source`.
+pub fn lint(source: &str) -> Vec<Diagnostic> {
+    let def = match parse(source) {
+        Ok(def) => def,
+        Err(diagnostic) => return vec![diagnostic],
+    };
+
+    let mut linter = Linter {
+        def: &def,
+        formatted: with_assigned_ids(&def),
+        locator: Locator::new(source),
+        diagnostics: Vec::new(),
+    };
+    linter.check_players();
+    linter.check_kinds();
+    linter.check_positions();
+    linter.check_usage();
+    linter.check_migrations();
+    linter.diagnostics
+}
+
+/// `def` with missing ids filled in as `GameDefBuilder` does with
+/// `IdAllocation::Stable`. In a list without ids that is the same as its
+/// default positional ids, but positional ids can repeat an id given
+/// explicitly further down the list, leaving the file invalid.
+fn with_assigned_ids(def: &GameDef) -> GameDef {
+    let mut def = def.clone();
+    def.assign_missing_ids_with(IdAllocation::Stable);
+    def
+}
+
+/// Rewrites the game definition in `source` canonically. Missing ids are
+/// numbered after the ids already in use, so formatting never renumbers.
+pub fn format(source: &str) -> Result<String, Diagnostic> {
+    let def = with_assigned_ids(&parse(source)?);
+    serde_yaml::to_string(&def)
+        .map(|s| s + "\n")
+        .map_err(|e| Diagnostic {
+            severity: Severity::Error,
+            line: None,
+            message: e.to_string(),
+            suggestion: None,
+        })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn clean_file_has_no_diagnostics() {
+        let source = "
+label: whist
+min_players: 3
+max_players: 5
+kind_defs:
+  - label: card
+    id: 1
+    suffix_range: {min: 1, max: 52}
+pos_defs:
+  - label: deck
+    id: 1
+";
+        assert_eq!(Vec::<Diagnostic>::new(), lint(source));
+    }
+
+    #[test]
+    fn reports_every_problem_with_its_line() {
+        let source = "label: whist
+min_players: 5
+max_players: 3
+kind_defs:
+  - label: card
+    id: 1
+    suffix_range:
+      min: 52
+      max: 1
+  - label: suit
+    id: 1
+    suffix_range: {min: 1, max: 4}
+    suffixes:
+      - label: hearts
+pos_defs:
+- label: deck
+  id: 1
+- label: deck
+  id: 12000
+";
+        let found: Vec<(Option<usize>, String)> = lint(source)
+            .into_iter()
+            .map(|d| (d.line, d.message))
+            .collect();
+
+        assert_eq!(
+            vec![
+                (Some(2), "min_players (5) is greater than max_players (3)".to_owned()),
+                (Some(5), "kind 'card' has an empty suffix_range 52..1".to_owned()),
+                (Some(10), "kind 'suit' has both a suffix_range and suffixes".to_owned()),
+                (Some(10), "kind 'suit' reuses id 1 of kind 'card'".to_owned()),
+                (Some(18), "position 'deck' has id 12000, ids must be between 1 and 9999".to_owned()),
+                (Some(18), "position 'deck' is defined more than once".to_owned()),
+            ],
+            found
+        );
+    }
+
+    #[test]
+    fn reports_alias_collisions() {
+        let source = "label: whist
+min_players: 3
+max_players: 5
+kind_defs:
+  - label: card
+    id: 1
+    aliases: [cards]
+  - label: cards
+    id: 2
+  - label: suit
+    id: 3
+    aliases: [card]
+pos_defs:
+  - label: deck
+    id: 1
+";
+        let found: Vec<(Option<usize>, String)> = lint(source)
+            .into_iter()
+            .map(|d| (d.line, d.message))
+            .collect();
+
+        assert_eq!(
+            vec![
+                (Some(8), "kind 'cards' is already an alias of kind 'card'".to_owned()),
+                (Some(10), "kind 'suit' has alias 'card', which is already used by kind 'card'".to_owned()),
+            ],
+            found
+        );
+    }
+
+    #[test]
+    fn reports_unknown_migration_targets() {
+        let source = "label: whist
+min_players: 3
+max_players: 5
+kind_defs:
+  - {label: spade, id: 1, suffix_range: {min: 1, max: 13}}
+pos_defs:
+  - {label: deck, id: 1}
+migrations:
+  - rename_pos: {from: talon, to: dekc}
+  - rename_pos: {from: pile, to: deck}
+  - map_kind_suffixes: {label: card, from: {min: 1, max: 13}, to: 1, into: spades}
+  - rename_kind: {from: card, to: joker}
+";
+        let rendered: Vec<String> = lint(source).iter().map(|d| d.to_string()).collect();
+
+        assert_eq!(
+            vec![
+                "9: error: migration moves rows to position 'dekc', which is not defined\n  help: did you mean 'deck'?",
+                "11: error: migration moves rows to kind 'spades', which is not defined\n  help: did you mean 'spade'?",
+                "12: error: migration moves rows to kind 'joker', which is not defined",
+            ],
+            rendered
+        );
+    }
+
+    #[test]
+    fn reports_suffix_maps_that_do_not_fit() {
+        let source = "label: whist
+min_players: 3
+max_players: 5
+kind_defs:
+  - {label: card, id: 1, suffix_range: {min: 1, max: 13}}
+pos_defs:
+  - {label: deck, id: 1}
+migrations:
+  - map_kind_suffixes: {label: card, from: {min: 13, max: 1}, to: 1}
+  - map_pos_suffixes: {label: deck, from: {min: 1, max: 13}, to: 2147483640}
+";
+        let rendered: Vec<String> = lint(source).iter().map(|d| d.to_string()).collect();
+
+        assert_eq!(
+            vec![
+                "9: error: migration maps the empty suffix range 13..1\n  help: min must not be more than max",
+                "10: error: migration maps suffixes past 2147483647\n  help: choose a smaller `to`",
+            ],
+            rendered
+        );
+    }
+
+    #[test]
+    fn suggests_fixes() {
+        let source = "
+label: solitaire
+min_players: 1
+max_players: 1
+kind_defs:
+  - label: card
+  - label: joker
+    id: 1
+  - label: jester
+    id: 1
+  - label: fool
+    id: 1
+pos_defs:
+  - {label: hand, id: 1, separate: true}
+";
+        let diagnostics = lint(source);
+        let rendered: Vec<String> = diagnostics.iter().map(|d| d.to_string()).collect();
+
+        assert_eq!(
+            vec![
+                "6: error: kind 'card' has no id\n  help: run `knott fmt` to assign one",
+                "9: error: kind 'jester' reuses id 1 of kind 'joker'\n  help: use id 3",
+                "11: error: kind 'fool' reuses id 1 of kind 'joker'\n  help: use id 4",
+                "14: warning: position 'hand' is separate but only one player can play\n  help: remove `separate: true`",
+            ],
+            rendered
+        );
+    }
+
+    #[test]
+    fn reports_yaml_errors_with_line() {
+        let diagnostics = lint("label: whist\nmin_players: [\n");
+        assert_eq!(1, diagnostics.len());
+        assert_eq!(Severity::Error, diagnostics[0].severity);
+        assert!(diagnostics[0].line.is_some());
+    }
+
+    #[test]
+    fn format_fills_in_ids() {
+        let source = "
+label: whist
+min_players: 3
+max_players: 5
+kind_defs: [{label: card, suffix_range: {min: 1, max: 52}}, {label: leader}]
+pos_defs:
+  - {label: deck, hidden: true}
+";
+        assert_eq!(
+            "---
+label: whist
+min_players: 3
+max_players: 5
+kind_defs:
+  - label: card
+    id: 1
+    suffix_range:
+      min: 1
+      max: 52
+  - label: leader
+    id: 2
+pos_defs:
+  - label: deck
+    id: 1
+    hidden: true
+",
+            format(source).unwrap()
+        );
+        assert_eq!(Vec::<Diagnostic>::new(), lint(&format(source).unwrap()));
+    }
+
+    #[test]
+    fn format_never_repeats_an_explicit_id() {
+        let source = "
+label: whist
+min_players: 3
+max_players: 5
+kind_defs: [{label: card}, {label: joker, id: 1}]
+pos_defs: [{label: deck, id: 1}]
+";
+        let formatted = format(source).unwrap();
+        assert!(formatted.contains("- label: card\n    id: 2\n"), "{}", formatted);
+        assert_eq!(Vec::<Diagnostic>::new(), lint(&formatted));
+
+        let mut positional: GameDef = serde_yaml::from_str(source).unwrap();
+        positional.assign_missing_ids();
+        assert_eq!(positional.kind_defs[0].id, positional.kind_defs[1].id);
+    }
+
+    #[test]
+    fn warns_about_unused_kinds_and_positions() {
+        let source = "
+label: whist
+min_players: 3
+max_players: 5
+kind_defs: [{label: card, id: 1}]
+pos_defs: []
+";
+        let rendered: Vec<String> = lint(source).iter().map(|d| d.to_string()).collect();
+        assert_eq!(
+            vec!["5: warning: kind 'card' is unused, the game has no positions to put it in\n  help: add a position to pos_defs"],
+            rendered
+        );
+
+        let source = "
+label: whist
+min_players: 3
+max_players: 5
+kind_defs: []
+pos_defs:
+  - {label: deck, id: 1}
+";
+        let rendered: Vec<String> = lint(source).iter().map(|d| d.to_string()).collect();
+        assert_eq!(
+            vec!["7: warning: position 'deck' is unused, the game has no kinds to put in it\n  help: add a kind to kind_defs"],
+            rendered
+        );
+    }
+}
@@ -1,9 +1,11 @@
-use std::collections::hash_map::{Entry, Values};
-use std::collections::HashMap;
+use std::collections::btree_map;
+use std::collections::hash_map;
+use std::collections::{BTreeMap, HashMap};
 use std::convert::TryFrom;
 use std::fmt;
 use std::fmt::Debug;
-use std::hash::Hash;
+use std::iter::Enumerate;
+use std::slice;
 
 pub trait HasId<I> {
     fn id(&self) -> I;
@@ -11,24 +13,222 @@ pub trait HasId<I> {
 
 pub trait Labelled {
     fn label(&self) -> &str;
+
+    /// Other labels the item can be found by.
+    fn aliases(&self) -> &[String] {
+        &[]
+    }
+}
+
+/// Items whose label can be changed, for `LookupTable::rename`.
+pub trait Relabel: Labelled {
+    fn set_label(&mut self, label: String);
+}
+
+/// Whether labels must match exactly or only up to case.
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum LabelCase {
+    Sensitive,
+    Insensitive,
+}
+
+fn fold_case(label: &str) -> String {
+    label.to_lowercase()
+}
+
+/// The number of characters to insert, delete, replace or swap with their
+/// neighbour to turn `a` into `b`.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    let mut rows = vec![(0..=b.len()).collect::<Vec<usize>>()];
+    for i in 1..=a.len() {
+        let mut row = vec![i; b.len() + 1];
+        for j in 1..=b.len() {
+            let cost = if a[i - 1] == b[j - 1] { 0 } else { 1 };
+            row[j] = (rows[i - 1][j] + 1)
+                .min(row[j - 1] + 1)
+                .min(rows[i - 1][j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                row[j] = row[j].min(rows[i - 2][j - 2] + 1);
+            }
+        }
+        rows.push(row);
+    }
+    rows[a.len()][b.len()]
+}
+
+/// The candidate closest to `label`, ignoring case, if it is close enough
+/// to be a likely typo: a third of the characters may be wrong, and at
+/// least one. The first candidate wins a tie.
+pub fn closest_label<'a, I>(label: &str, candidates: I) -> Option<&'a str>
+    where
+        I: IntoIterator<Item=&'a str>,
+{
+    let folded = fold_case(label);
+    let limit = (folded.chars().count() / 3).max(1);
+    candidates.into_iter()
+        .map(|c| (edit_distance(&folded, &fold_case(c)), c))
+        .filter(|(distance, _)| *distance <= limit)
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, c)| c)
 }
 
+/// Ids that can double as indexes into a vector, in the same order.
+pub trait DenseId: Ord + Copy {
+    fn dense_index(&self) -> Option<usize>;
+
+    /// The id at `index`; only called with indexes `dense_index` returned.
+    fn from_dense_index(index: usize) -> Self;
+}
+
+impl DenseId for u32 {
+    fn dense_index(&self) -> Option<usize> {
+        Some(*self as usize)
+    }
+
+    fn from_dense_index(index: usize) -> Self {
+        index as u32
+    }
+}
+
+/// Whether a vector reaching `index` is worth keeping for `len` values.
+fn fits_dense(index: usize, len: usize) -> bool {
+    index < 2 * len + 64
+}
+
+#[derive(Clone)]
+enum Repr<I, V> {
+    Dense(Vec<Option<V>>),
+    Sparse(BTreeMap<I, V>),
+}
+
+/// Values by id, kept in a vector indexed by id while the ids are small
+/// for how many there are, and in a tree once they are not.
+#[derive(Clone)]
+pub(crate) struct IdStore<I, V> {
+    repr: Repr<I, V>,
+    len: usize,
+}
+
+impl<I: DenseId, V> IdStore<I, V> {
+    pub(crate) fn new() -> IdStore<I, V> {
+        IdStore { repr: Repr::Dense(Vec::new()), len: 0 }
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.len
+    }
+
+    pub(crate) fn get(&self, id: &I) -> Option<&V> {
+        match &self.repr {
+            Repr::Dense(values) => id.dense_index()
+                .and_then(|i| values.get(i))
+                .and_then(Option::as_ref),
+            Repr::Sparse(values) => values.get(id),
+        }
+    }
+
+    /// Inserts `value` unless `id` is taken, in which case it is handed back.
+    pub(crate) fn insert(&mut self, id: I, value: V) -> Result<(), V> {
+        if self.get(&id).is_some() {
+            return Err(value);
+        }
+        let index = id.dense_index().filter(|i| fits_dense(*i, self.len + 1));
+        match (&mut self.repr, index) {
+            (Repr::Dense(values), Some(index)) => {
+                if values.len() <= index {
+                    values.resize_with(index + 1, || None);
+                }
+                values[index] = Some(value);
+            }
+            (Repr::Dense(values), None) => {
+                let mut sparse: BTreeMap<I, V> = values.drain(..)
+                    .enumerate()
+                    .filter_map(|(i, v)| v.map(|v| (I::from_dense_index(i), v)))
+                    .collect();
+                sparse.insert(id, value);
+                self.repr = Repr::Sparse(sparse);
+            }
+            (Repr::Sparse(values), _) => {
+                values.insert(id, value);
+            }
+        }
+        self.len += 1;
+        Ok(())
+    }
+
+    pub(crate) fn remove(&mut self, id: &I) -> Option<V> {
+        let removed = match &mut self.repr {
+            Repr::Dense(values) => id.dense_index()
+                .and_then(|i| values.get_mut(i))
+                .and_then(Option::take),
+            Repr::Sparse(values) => values.remove(id),
+        };
+        if removed.is_some() {
+            self.len -= 1;
+        }
+        removed
+    }
+
+    /// The ids and values in id order.
+    pub(crate) fn iter(&self) -> Iter<'_, I, V> {
+        match &self.repr {
+            Repr::Dense(values) => Iter::Dense(values.iter().enumerate()),
+            Repr::Sparse(values) => Iter::Sparse(values.iter()),
+        }
+    }
+}
+
+pub enum Iter<'a, I, V> {
+    Dense(Enumerate<slice::Iter<'a, Option<V>>>),
+    Sparse(btree_map::Iter<'a, I, V>),
+}
+
+impl<'a, I: DenseId, V> Iterator for Iter<'a, I, V> {
+    type Item = (I, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self {
+            Iter::Dense(values) => values
+                .find_map(|(i, v)| v.as_ref().map(|v| (I::from_dense_index(i), v))),
+            Iter::Sparse(values) => values.next().map(|(i, v)| (*i, v)),
+        }
+    }
+}
+
+/// The items of a `LookupTable` in id order.
+pub struct Values<'a, I, V>(Iter<'a, I, V>);
+
+impl<'a, I: DenseId, V> Iterator for Values<'a, I, V> {
+    type Item = &'a V;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.0.next().map(|(_, v)| v)
+    }
+}
+
+/// Items found by id or by label, or by any of their aliases. Iteration
+/// is in id order.
 pub struct LookupTable<I, V>
     where
-        I: Hash + PartialEq
+        I: DenseId
 {
-    values: HashMap<I, V>,
+    values: IdStore<I, V>,
     label_index: HashMap<String, I>,
+    /// Labels and aliases in lower case, or `None` where items differing
+    /// only in case share one.
+    folded_index: HashMap<String, Option<I>>,
 }
 
 
 impl<I, V> fmt::Debug for LookupTable<I, V>
     where
-        I: Hash + PartialEq,
+        I: DenseId,
         V: Debug,
 {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        for value in self.values.values() {
+        for (_, value) in self.values.iter() {
             write!(f, "\n{:?}", value)?;
         }
         Ok(())
@@ -36,66 +236,230 @@ impl<I, V> fmt::Debug for LookupTable<I, V>
 }
 
 impl<'a, I, V> IntoIterator for &'a LookupTable<I, V> where
-    I: Hash + PartialEq + Eq {
+    I: DenseId {
     type Item = &'a V;
     type IntoIter = Values<'a, I, V>;
 
     fn into_iter(self) -> Self::IntoIter {
-        self.values.values()
+        Values(self.values.iter())
     }
 }
 
+/// `label` followed by `aliases`, unless one of them repeats another.
+fn labels_of<'a, I>(label: &'a str, aliases: &'a [String]) -> Result<Vec<&'a str>, Collision<I>>
+    where
+        I: Debug + PartialEq,
+{
+    let mut labels = vec![label];
+    for alias in aliases {
+        if labels.contains(&alias.as_str()) {
+            return Err(Collision::LabelCollision(alias.clone()));
+        }
+        labels.push(alias);
+    }
+    Ok(labels)
+}
+
 impl<I, V> LookupTable<I, V>
     where
-        I: Hash + PartialEq + Eq + Debug,
+        I: DenseId + Debug,
         V: Labelled + HasId<I>,
 {
     pub fn new() -> LookupTable<I, V> {
         LookupTable {
-            values: HashMap::new(),
+            values: IdStore::new(),
             label_index: HashMap::new(),
+            folded_index: HashMap::new(),
         }
     }
 
     pub fn with_capacity(capacity: usize) -> LookupTable<I, V> {
         LookupTable {
-            values: HashMap::with_capacity(capacity),
+            values: IdStore::new(),
             label_index: HashMap::with_capacity(capacity),
+            folded_index: HashMap::with_capacity(capacity),
         }
     }
 
-    pub fn push(&mut self, item: V) -> Result<(), Collision<I>> {
-        if let Entry::Vacant(e) = self.values.entry(item.id()) {
-            if let Entry::Vacant(e2) = self.label_index.entry(item.label().to_owned()) {
-                e2.insert(item.id());
-                e.insert(item);
-                Ok(())
-            } else {
-                Err(Collision::LabelCollision(item.label().to_owned()))
+    /// Fails if any of `labels` belongs to an item other than `id`.
+    fn check_labels(&self, labels: &[&str], id: I) -> Result<(), Collision<I>> {
+        match labels.iter().find(|l| self.label_index.get(**l).is_some_and(|owner| *owner != id)) {
+            Some(taken) => Err(Collision::LabelCollision((*taken).to_owned())),
+            None => Ok(()),
+        }
+    }
+
+    fn index_labels(&mut self, labels: &[&str], id: I) {
+        for label in labels {
+            self.label_index.insert((*label).to_owned(), id);
+            match self.folded_index.entry(fold_case(label)) {
+                hash_map::Entry::Vacant(e) => {
+                    e.insert(Some(id));
+                }
+                hash_map::Entry::Occupied(mut e) => {
+                    if *e.get() != Some(id) {
+                        e.insert(None);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Drops the labels and aliases of `item` from the indexes. Labels that
+    /// were shared up to case are looked up again among the items left.
+    fn unindex_labels(&mut self, item: &V) {
+        let labels = std::iter::once(item.label()).chain(item.aliases().iter().map(String::as_str));
+        for label in labels {
+            self.label_index.remove(label);
+            let folded = fold_case(label);
+            let mut owners = self.label_index
+                .iter()
+                .filter(|(other, _)| fold_case(other) == folded)
+                .map(|(_, id)| *id);
+            match owners.next() {
+                None => {
+                    self.folded_index.remove(&folded);
+                }
+                Some(first) => {
+                    let owner = if owners.all(|id| id == first) { Some(first) } else { None };
+                    self.folded_index.insert(folded, owner);
+                }
             }
-        } else {
-            Err(Collision::IdCollision(item.id()))
         }
     }
 
+    /// Adds `item` unless its id, or its label or one of its aliases, is
+    /// already taken, in which case the table is left unchanged.
+    pub fn push(&mut self, item: V) -> Result<(), Collision<I>> {
+        let id = item.id();
+        if self.values.get(&id).is_some() {
+            return Err(Collision::IdCollision(id));
+        }
+        let labels = labels_of(item.label(), item.aliases())?;
+        self.check_labels(&labels, id)?;
+        self.index_labels(&labels, id);
+        self.values.insert(id, item).ok();
+        Ok(())
+    }
+
+    /// Puts `item` in place of the item with its id, returning that item,
+    /// or adds it if there is none. Fails, leaving the table unchanged, if
+    /// its label or one of its aliases belongs to another item.
+    pub fn replace(&mut self, item: V) -> Result<Option<V>, Collision<I>> {
+        let id = item.id();
+        let labels = labels_of(item.label(), item.aliases())?;
+        self.check_labels(&labels, id)?;
+        let old = self.values.remove(&id);
+        if let Some(old) = &old {
+            self.unindex_labels(old);
+        }
+        self.index_labels(&labels, id);
+        self.values.insert(id, item).ok();
+        Ok(old)
+    }
+
+    /// Removes and returns the item with `id`.
+    pub fn remove(&mut self, id: &I) -> Option<V> {
+        let item = self.values.remove(id)?;
+        self.unindex_labels(&item);
+        Some(item)
+    }
+
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The items in id order.
+    pub fn iter(&self) -> Values<'_, I, V> {
+        Values(self.values.iter())
+    }
+
     pub fn contains_id(&self, index: &I) -> bool {
-        self.values.contains_key(index)
+        self.values.get(index).is_some()
     }
 
     pub fn find(&self, index: &I) -> Option<&V> {
         self.values.get(index)
     }
 
+    /// Finds the item with `label` as its label or one of its aliases.
     pub fn find_by_label<T: AsRef<str>>(&self, label: T) -> Option<&V> {
         self.label_index
             .get(label.as_ref())
-            .map(|i| &self.values[i])
+            .and_then(|i| self.values.get(i))
+    }
+
+    /// The label or alias nearest to `label`, to suggest when it is not found.
+    pub fn suggest_label<T: AsRef<str>>(&self, label: T) -> Option<&str> {
+        let candidates = self.values.iter()
+            .flat_map(|(_, v)| std::iter::once(v.label()).chain(v.aliases().iter().map(String::as_str)));
+        closest_label(label.as_ref(), candidates)
+    }
+
+    /// As `find_by_label`, but with `LabelCase::Insensitive` an exact match
+    /// is preferred and otherwise any match up to case will do, unless it
+    /// is shared by more than one item.
+    pub fn find_by_label_with<T: AsRef<str>>(&self, label: T, case: LabelCase) -> Option<&V> {
+        let label = label.as_ref();
+        match case {
+            LabelCase::Sensitive => self.find_by_label(label),
+            LabelCase::Insensitive => self.find_by_label(label).or_else(|| {
+                self.folded_index
+                    .get(&fold_case(label))
+                    .copied()
+                    .flatten()
+                    .and_then(|i| self.values.get(&i))
+            }),
+        }
+    }
+}
+
+impl<I, V> LookupTable<I, V>
+    where
+        I: DenseId + Debug,
+        V: Relabel + HasId<I>,
+{
+    /// Gives the item with `id` the label `label`, returning its old label,
+    /// or `None` if there is no such item. Fails, leaving the table
+    /// unchanged, if `label` belongs to another item or is one of the
+    /// item's own aliases.
+    pub fn rename<T: AsRef<str>>(&mut self, id: &I, label: T) -> Result<Option<String>, Collision<I>> {
+        let label = label.as_ref();
+        match self.values.get(id) {
+            None => return Ok(None),
+            Some(item) => {
+                let labels = labels_of(label, item.aliases())?;
+                self.check_labels(&labels, *id)?;
+            }
+        }
+        let mut item = self.values.remove(id).expect("item was found");
+        self.unindex_labels(&item);
+        let old = item.label().to_owned();
+        item.set_label(label.to_owned());
+        let labels = labels_of::<I>(item.label(), item.aliases()).expect("labels were checked");
+        self.index_labels(&labels, *id);
+        self.values.insert(*id, item).ok();
+        Ok(Some(old))
+    }
+}
+
+impl<I, V> Default for LookupTable<I, V>
+    where
+        I: DenseId + Debug,
+        V: Labelled + HasId<I>,
+{
+    fn default() -> Self {
+        LookupTable::new()
     }
 }
 
 impl<I, V> TryFrom<Vec<V>> for LookupTable<I, V>
     where
-        I: Hash + PartialEq + Eq + Debug,
+        I: DenseId + Debug,
         V: Labelled + HasId<I>,
 {
     type Error = Collision<I>;
@@ -135,6 +499,43 @@ mod test {
         }
     }
 
+    #[derive(Debug, Clone, PartialEq)]
+    struct Aliased {
+        id: u32,
+        label: String,
+        aliases: Vec<String>,
+    }
+
+    impl Labelled for Aliased {
+        fn label(&self) -> &str {
+            &self.label
+        }
+
+        fn aliases(&self) -> &[String] {
+            &self.aliases
+        }
+    }
+
+    impl Relabel for Aliased {
+        fn set_label(&mut self, label: String) {
+            self.label = label;
+        }
+    }
+
+    impl HasId<u32> for Aliased {
+        fn id(&self) -> u32 {
+            self.id
+        }
+    }
+
+    fn aliased(id: u32, label: &str, aliases: &[&str]) -> Aliased {
+        Aliased {
+            id,
+            label: label.to_owned(),
+            aliases: aliases.iter().map(|a| (*a).to_owned()).collect(),
+        }
+    }
+
     impl HasId<u32> for Dummy {
         fn id(&self) -> u32 {
             self.id
@@ -173,6 +574,148 @@ mod test {
         assert_eq!(None, lookup.find(&5));
     }
 
+    #[test]
+    fn iterates_in_id_order() {
+        let items: Vec<Dummy> = [(4, "B"), (1, "A"), (3, "C")]
+            .iter()
+            .map(|(id, label)| Dummy { id: *id, label: (*label).to_owned() })
+            .collect();
+
+        let lookup: LookupTable<u32, Dummy> = items.try_into().unwrap();
+
+        let ids: Vec<u32> = lookup.iter().map(|d| d.id).collect();
+        assert_eq!(vec![1, 3, 4], ids);
+        assert_eq!(
+            "\nDummy { id: 1, label: \"A\" }\nDummy { id: 3, label: \"C\" }\nDummy { id: 4, label: \"B\" }",
+            format!("{:?}", lookup)
+        );
+    }
+
+    #[test]
+    fn far_apart_ids_fall_back_to_sparse_storage() {
+        let items: Vec<Dummy> = [(9000, "B"), (2, "A"), (70_000, "C")]
+            .iter()
+            .map(|(id, label)| Dummy { id: *id, label: (*label).to_owned() })
+            .collect();
+
+        let lookup: LookupTable<u32, Dummy> = items.try_into().unwrap();
+
+        assert!(matches!(lookup.values.repr, Repr::Sparse(_)));
+        assert_eq!(Some("C"), lookup.find(&70_000).map(|d| d.label.as_str()));
+        assert_eq!(None, lookup.find(&3));
+        let ids: Vec<u32> = lookup.iter().map(|d| d.id).collect();
+        assert_eq!(vec![2, 9000, 70_000], ids);
+    }
+
+    #[test]
+    fn can_find_by_alias_and_ignoring_case() {
+        let items = vec![
+            aliased(1, "hearts", &["♥", "heart"]),
+            aliased(2, "clubs", &["♣"]),
+            aliased(3, "Jack", &[]),
+            aliased(4, "jack", &[]),
+        ];
+
+        let lookup: LookupTable<u32, Aliased> = items.try_into().unwrap();
+
+        assert_eq!(Some(1), lookup.find_by_label("♥").map(|a| a.id));
+        assert_eq!(Some(1), lookup.find_by_label("heart").map(|a| a.id));
+        assert_eq!(None, lookup.find_by_label("Hearts"));
+        assert_eq!(Some(1), lookup.find_by_label_with("HEART", LabelCase::Insensitive).map(|a| a.id));
+        assert_eq!(None, lookup.find_by_label_with("Clubs", LabelCase::Sensitive));
+        assert_eq!(Some(2), lookup.find_by_label_with("Clubs", LabelCase::Insensitive).map(|a| a.id));
+
+        // Exact matches win; otherwise labels shared up to case match nothing.
+        assert_eq!(Some(3), lookup.find_by_label_with("Jack", LabelCase::Insensitive).map(|a| a.id));
+        assert_eq!(Some(4), lookup.find_by_label_with("jack", LabelCase::Insensitive).map(|a| a.id));
+        assert_eq!(None, lookup.find_by_label_with("JACK", LabelCase::Insensitive));
+    }
+
+    #[test]
+    fn suggests_nearest_label() {
+        let items = vec![
+            aliased(1, "hand", &[]),
+            aliased(2, "deck", &["talon"]),
+            aliased(3, "discard", &[]),
+        ];
+
+        let lookup: LookupTable<u32, Aliased> = items.try_into().unwrap();
+
+        assert_eq!(Some("hand"), lookup.suggest_label("hnad"));
+        assert_eq!(Some("hand"), lookup.suggest_label("Hands"));
+        assert_eq!(Some("talon"), lookup.suggest_label("tallon"));
+        assert_eq!(Some("discard"), lookup.suggest_label("dscrad"));
+        assert_eq!(None, lookup.suggest_label("trick"));
+        assert_eq!(None, lookup.suggest_label("x"));
+    }
+
+    #[test]
+    fn can_remove_replace_and_rename() {
+        let items = vec![aliased(1, "hearts", &["♥"]), aliased(2, "clubs", &["♣"]), aliased(3, "Jack", &[])];
+        let mut lookup: LookupTable<u32, Aliased> = items.try_into().unwrap();
+
+        assert_eq!(Some("hearts".to_owned()), lookup.remove(&1).map(|a| a.label));
+        assert_eq!(None, lookup.remove(&1));
+        assert_eq!(None, lookup.find_by_label("♥"));
+        assert_eq!(2, lookup.len());
+
+        assert_eq!(Ok(None), lookup.replace(aliased(1, "hearts", &["♡"])));
+        assert_eq!(Some(1), lookup.find_by_label("♡").map(|a| a.id));
+        let old = lookup.replace(aliased(2, "spades", &["♠"])).unwrap();
+        assert_eq!(Some("clubs".to_owned()), old.map(|a| a.label));
+        assert_eq!(None, lookup.find_by_label("♣"));
+        assert_eq!(Some(2), lookup.find_by_label("spades").map(|a| a.id));
+
+        assert_eq!(Ok(Some("spades".to_owned())), lookup.rename(&2, "jack"));
+        assert_eq!(Some(2), lookup.find_by_label("jack").map(|a| a.id));
+        assert_eq!(None, lookup.find_by_label("spades"));
+        assert_eq!(None, lookup.find_by_label_with("JACK", LabelCase::Insensitive));
+        assert_eq!(Ok(None), lookup.rename(&7, "seven"));
+
+        // Once the clash goes, labels match up to case again.
+        lookup.remove(&3);
+        assert_eq!(Some(2), lookup.find_by_label_with("JACK", LabelCase::Insensitive).map(|a| a.id));
+
+        let ids: Vec<u32> = lookup.iter().map(|a| a.id).collect();
+        assert_eq!(vec![1, 2], ids);
+    }
+
+    #[test]
+    fn failed_changes_leave_the_table_unchanged() {
+        let items = vec![aliased(1, "hearts", &["♥"]), aliased(2, "clubs", &["♣"])];
+        let mut lookup: LookupTable<u32, Aliased> = items.try_into().unwrap();
+
+        assert_eq!(Err(Collision::LabelCollision("♥".to_owned())), lookup.rename(&2, "♥"));
+        assert_eq!(Err(Collision::LabelCollision("♣".to_owned())), lookup.rename(&2, "♣"));
+        assert_eq!(
+            Err(Collision::LabelCollision("hearts".to_owned())),
+            lookup.replace(aliased(2, "diamonds", &["hearts"]))
+        );
+        assert_eq!(Ok(Some("clubs".to_owned())), lookup.rename(&2, "clubs"));
+
+        assert_eq!(Some(2), lookup.find_by_label("clubs").map(|a| a.id));
+        assert_eq!(Some(2), lookup.find_by_label("♣").map(|a| a.id));
+        assert_eq!(Some(1), lookup.find_by_label("hearts").map(|a| a.id));
+        assert_eq!(None, lookup.find_by_label("diamonds"));
+        assert_eq!(2, lookup.len());
+        assert!(!lookup.is_empty());
+    }
+
+    #[test]
+    fn can_not_convert_if_an_alias_collides() {
+        let with_label = vec![aliased(1, "hearts", &[]), aliased(2, "clubs", &["hearts"])];
+        let result: Result<LookupTable<u32, Aliased>, Collision<u32>> = with_label.try_into();
+        assert_eq!(Collision::LabelCollision("hearts".to_owned()), result.unwrap_err());
+
+        let with_alias = vec![aliased(1, "hearts", &["♥"]), aliased(2, "♥", &[])];
+        let result: Result<LookupTable<u32, Aliased>, Collision<u32>> = with_alias.try_into();
+        assert_eq!(Collision::LabelCollision("♥".to_owned()), result.unwrap_err());
+
+        let with_itself = vec![aliased(1, "hearts", &["♥", "♥"])];
+        let result: Result<LookupTable<u32, Aliased>, Collision<u32>> = with_itself.try_into();
+        assert_eq!(Collision::LabelCollision("♥".to_owned()), result.unwrap_err());
+    }
+
     #[test]
     fn can_not_convert_if_there_is_a_label_collision() {
         let a1 = Dummy {
@@ -0,0 +1,376 @@
+use std::collections::BTreeMap;
+
+use rand::seq::SliceRandom;
+use rand::Rng;
+
+use crate::coords::QKind;
+use crate::specs::{GameSpec, PlayerNum};
+use crate::state::{Cmd, State};
+use crate::view::{DeterminizeError, Determinizer};
+
+/// The rules of a game, as far as the search needs to know them.
+pub trait MoveGen {
+    /// The player whose turn it is, or `None` once the game is over.
+    fn to_move(&self, spec: &GameSpec, state: &State) -> Option<PlayerNum>;
+
+    fn legal_moves(&self, spec: &GameSpec, state: &State, player: PlayerNum) -> Vec<Cmd>;
+
+    /// How well `player` did in a finished game, from 0.0 (lost) to 1.0 (won).
+    fn reward(&self, spec: &GameSpec, state: &State, player: PlayerNum) -> f64;
+}
+
+struct Node {
+    cmd: Option<Cmd>,
+    player: Option<PlayerNum>,
+    parent: Option<usize>,
+    children: Vec<usize>,
+    untried: Vec<Cmd>,
+    visits: u32,
+    reward: f64,
+}
+
+impl Node {
+    fn new(cmd: Option<Cmd>, player: Option<PlayerNum>, parent: Option<usize>) -> Node {
+        Node {
+            cmd,
+            player,
+            parent,
+            children: Vec::new(),
+            untried: Vec::new(),
+            visits: 0,
+            reward: 0.0,
+        }
+    }
+
+    fn uct(&self, parent_visits: u32, exploration: f64) -> f64 {
+        let visits = self.visits as f64;
+        self.reward / visits + exploration * ((parent_visits as f64).ln() / visits).sqrt()
+    }
+}
+
+pub struct Mcts<G> {
+    move_gen: G,
+    iterations: usize,
+    worlds: usize,
+    exploration: f64,
+}
+
+/// Applies `cmd` to `state`, leaving it untouched if the command fails.
+fn play(state: &mut State, cmd: &Cmd) -> bool {
+    let mut tx = state.start_tx();
+    if tx.apply(cmd).is_ok() {
+        tx.commit();
+        true
+    } else {
+        tx.rollback();
+        false
+    }
+}
+
+/// How many of each piece there are in `state`, or `None` if there are more
+/// than `u32::MAX` of one.
+fn totals(state: &State) -> Option<Vec<(QKind, u32)>> {
+    let mut totals: BTreeMap<QKind, u32> = BTreeMap::new();
+    for row in state.export_rows() {
+        let total = totals.entry(row.kind).or_insert(0);
+        *total = total.checked_add(row.count)?;
+    }
+    Some(totals.into_iter().collect())
+}
+
+impl<G> Mcts<G>
+    where
+        G: MoveGen,
+{
+    pub fn new(move_gen: G) -> Mcts<G> {
+        Mcts {
+            move_gen,
+            iterations: 1000,
+            worlds: 1,
+            exploration: std::f64::consts::SQRT_2,
+        }
+    }
+
+    /// The number of playouts to run in each sampled world.
+    pub fn iterations(mut self, iterations: usize) -> Self {
+        self.iterations = iterations;
+        self
+    }
+
+    /// The number of worlds to sample when the player cannot see everything.
+    pub fn worlds(mut self, worlds: usize) -> Self {
+        self.worlds = worlds;
+        self
+    }
+
+    pub fn exploration(mut self, exploration: f64) -> Self {
+        self.exploration = exploration;
+        self
+    }
+
+    /// The move the player to move should make, or `None` if there is none or
+    /// there are too many pieces to sample worlds from.
+    pub fn best_move<R: Rng>(&self, spec: &GameSpec, state: &State, rng: &mut R) -> Option<Cmd> {
+        let player = self.move_gen.to_move(spec, state)?;
+        let view = state.player_view(spec, player);
+        let determinizer = match Determinizer::new(&view, &totals(state)?) {
+            Err(DeterminizeError::TooManyPieces) => return None,
+            result => result.expect("a state is consistent with every view of it"),
+        };
+
+        let mut visits: Vec<(Cmd, u32)> = Vec::new();
+        for _ in 0..self.worlds.max(1) {
+            let world = determinizer.sample(spec, rng);
+            for (cmd, n) in self.search(spec, &world, player, rng) {
+                match visits.iter_mut().find(|(c, _)| *c == cmd) {
+                    Some((_, total)) => *total += n,
+                    None => visits.push((cmd, n)),
+                }
+            }
+        }
+
+        visits.into_iter()
+            .max_by_key(|(_, visits)| *visits)
+            .map(|(cmd, _)| cmd)
+    }
+
+    /// Runs the search in a single world and returns the visits of each root move.
+    fn search<R: Rng>(
+        &self,
+        spec: &GameSpec,
+        world: &State,
+        player: PlayerNum,
+        rng: &mut R,
+    ) -> Vec<(Cmd, u32)> {
+        let mut nodes = vec![Node::new(None, None, None)];
+        nodes[0].untried = self.move_gen.legal_moves(spec, world, player);
+
+        'iterations: for _ in 0..self.iterations {
+            let mut state = world.clone();
+            let mut current = 0;
+
+            // Selection
+            while nodes[current].untried.is_empty() && !nodes[current].children.is_empty() {
+                let parent = current;
+                let parent_visits = nodes[parent].visits;
+                current = *nodes[parent].children
+                    .iter()
+                    .max_by(|a, b| {
+                        let a = nodes[**a].uct(parent_visits, self.exploration);
+                        let b = nodes[**b].uct(parent_visits, self.exploration);
+                        a.total_cmp(&b)
+                    })
+                    .unwrap();
+                if let Some(cmd) = &nodes[current].cmd {
+                    // A move the rules offered but the state refuses is dropped.
+                    if !play(&mut state, cmd) {
+                        nodes[parent].children.retain(|c| *c != current);
+                        continue 'iterations;
+                    }
+                }
+            }
+
+            // Expansion
+            if !nodes[current].untried.is_empty() {
+                let index = rng.gen_range(0..nodes[current].untried.len());
+                let cmd = nodes[current].untried.swap_remove(index);
+                let mover = self.move_gen.to_move(spec, &state);
+                if !play(&mut state, &cmd) {
+                    continue;
+                }
+
+                let mut child = Node::new(Some(cmd), mover, Some(current));
+                if let Some(next) = self.move_gen.to_move(spec, &state) {
+                    child.untried = self.move_gen.legal_moves(spec, &state, next);
+                }
+                nodes.push(child);
+                let child = nodes.len() - 1;
+                nodes[current].children.push(child);
+                current = child;
+            }
+
+            // Rollout
+            while let Some(next) = self.move_gen.to_move(spec, &state) {
+                let moves = self.move_gen.legal_moves(spec, &state, next);
+                match moves.choose(rng) {
+                    Some(cmd) if play(&mut state, cmd) => {}
+                    _ => break,
+                }
+            }
+
+            // Backpropagation
+            let mut node = Some(current);
+            while let Some(index) = node {
+                let n = &mut nodes[index];
+                n.visits += 1;
+                if let Some(mover) = n.player {
+                    n.reward += self.move_gen.reward(spec, &state, mover);
+                }
+                node = n.parent;
+            }
+        }
+
+        nodes[0].children
+            .iter()
+            .filter_map(|c| nodes[*c].cmd.clone().map(|cmd| (cmd, nodes[*c].visits)))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::coords::{QPos, Region, Suffix};
+    use crate::defs::{GameDefBuilder, KindDef, PosDef};
+    use crate::state::{CreatePieces, MovePieces};
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+    use std::convert::TryInto;
+
+    /// Players take turns adding a token worth one or two points to the pile;
+    /// whoever brings the pile to ten wins.
+    struct RaceToTen;
+
+    impl RaceToTen {
+        fn spec() -> GameSpec {
+            GameDefBuilder::bld("race")
+                .kind(KindDef::bld("token").suffix_range(1, 2))
+                .pos(PosDef::bld("pile"))
+                .build()
+                .try_into()
+                .unwrap()
+        }
+
+        fn add(value: i32) -> Cmd {
+            Cmd::CreatePieces(CreatePieces {
+                pos: QPos { pos: 1.try_into().unwrap(), region: Region(0), suffix: Suffix(0) },
+                kind: QKind { kind: 1.try_into().unwrap(), suffix: Suffix(value) },
+                count: 1,
+            })
+        }
+
+        fn tokens_and_total(state: &State) -> (u32, u32) {
+            state.export_rows()
+                .iter()
+                .fold((0, 0), |(n, total), row| {
+                    (n + row.count, total + row.count * row.kind.suffix.0 as u32)
+                })
+        }
+    }
+
+    impl MoveGen for RaceToTen {
+        fn to_move(&self, _spec: &GameSpec, state: &State) -> Option<PlayerNum> {
+            let (tokens, total) = Self::tokens_and_total(state);
+            if total >= 10 {
+                None
+            } else {
+                Some(PlayerNum((tokens % 2) as u8))
+            }
+        }
+
+        fn legal_moves(&self, _spec: &GameSpec, state: &State, _player: PlayerNum) -> Vec<Cmd> {
+            let (_, total) = Self::tokens_and_total(state);
+            if total == 9 {
+                vec![Self::add(1)]
+            } else {
+                vec![Self::add(1), Self::add(2)]
+            }
+        }
+
+        fn reward(&self, _spec: &GameSpec, state: &State, player: PlayerNum) -> f64 {
+            let (tokens, _) = Self::tokens_and_total(state);
+            let winner = PlayerNum(((tokens + 1) % 2) as u8);
+            if winner == player { 1.0 } else { 0.0 }
+        }
+    }
+
+    #[test]
+    fn finds_winning_move() {
+        let spec = RaceToTen::spec();
+        let mut state = State::new(&spec);
+        let mut rng = StdRng::seed_from_u64(7);
+        let mcts = Mcts::new(RaceToTen).iterations(2000);
+
+        // Leaving a multiple of three points to go wins.
+        assert_eq!(Some(RaceToTen::add(1)), mcts.best_move(&spec, &state, &mut rng));
+
+        play(&mut state, &RaceToTen::add(2));
+        assert_eq!(Some(RaceToTen::add(2)), mcts.best_move(&spec, &state, &mut rng));
+    }
+
+    /// Player 0 guesses the card in player 1's hidden hand. The cards they
+    /// cannot see are one 2 and three 3s, split between that hand and the deck.
+    struct Guess;
+
+    impl Guess {
+        fn spec() -> GameSpec {
+            GameDefBuilder::bld("guess")
+                .kind(KindDef::bld("card").suffix_range(1, 3))
+                .kind(KindDef::bld("guess").suffix_range(1, 3))
+                .pos(PosDef::bld("deck").hidden())
+                .pos(PosDef::bld("hand").hidden().separate())
+                .pos(PosDef::bld("table"))
+                .build()
+                .try_into()
+                .unwrap()
+        }
+
+        fn qpos(pos: u32, region: u16) -> QPos {
+            QPos { pos: pos.try_into().unwrap(), region: Region(region), suffix: Suffix(0) }
+        }
+
+        fn create(pos: QPos, kind: u32, suffix: i32) -> Cmd {
+            Cmd::CreatePieces(CreatePieces {
+                pos,
+                kind: QKind { kind: kind.try_into().unwrap(), suffix: Suffix(suffix) },
+                count: 1,
+            })
+        }
+
+        fn guess(card: i32) -> Cmd {
+            Self::create(Self::qpos(3, 0), 2, card)
+        }
+    }
+
+    impl MoveGen for Guess {
+        fn to_move(&self, _spec: &GameSpec, state: &State) -> Option<PlayerNum> {
+            let guessed = state.export_rows().iter().any(|r| u32::from(r.kind.kind) == 2);
+            if guessed { None } else { Some(PlayerNum(0)) }
+        }
+
+        fn legal_moves(&self, _spec: &GameSpec, _state: &State, _player: PlayerNum) -> Vec<Cmd> {
+            // Taking back a guess that was never made always fails.
+            let take_back = Cmd::MovePieces(MovePieces {
+                from: Self::qpos(3, 0),
+                to: Self::qpos(1, 0),
+                kind: QKind { kind: 2.try_into().unwrap(), suffix: Suffix(1) },
+                count: 1,
+            });
+            vec![Self::guess(1), Self::guess(2), Self::guess(3), take_back]
+        }
+
+        fn reward(&self, _spec: &GameSpec, state: &State, _player: PlayerNum) -> f64 {
+            let rows = state.export_rows();
+            let held = rows.iter().find(|r| r.pos == Self::qpos(2, 1)).map(|r| r.kind.suffix);
+            let guessed = rows.iter().find(|r| u32::from(r.kind.kind) == 2).map(|r| r.kind.suffix);
+            if held.is_some() && held == guessed { 1.0 } else { 0.0 }
+        }
+    }
+
+    #[test]
+    fn searches_sampled_worlds_of_hidden_positions() {
+        let spec = Guess::spec();
+        let mut state = State::new(&spec);
+        for (pos, card) in [(Guess::qpos(2, 0), 1), (Guess::qpos(2, 1), 2)] {
+            play(&mut state, &Guess::create(pos, 1, card));
+        }
+        for _ in 0..3 {
+            play(&mut state, &Guess::create(Guess::qpos(1, 0), 1, 3));
+        }
+        let mut rng = StdRng::seed_from_u64(7);
+
+        // Player 1 really holds the 2, but a 3 is the likelier guess.
+        let mcts = Mcts::new(Guess).iterations(200).worlds(16);
+        assert_eq!(Some(Guess::guess(3)), mcts.best_move(&spec, &state, &mut rng));
+    }
+}
@@ -0,0 +1,412 @@
+//! Upgrades the rows of a saved game to a new version of its definition,
+//! following the migrations listed in the new definition.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::convert::TryFrom;
+
+use crate::coords::{Kind, Pos, QKind, QPos, Suffix};
+use crate::defs::{MigrationDef, SuffixMapDef};
+use crate::specs::GameSpec;
+use crate::state::ExportRow;
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum MigrateError {
+    /// The row's position has no counterpart in the new definition: the
+    /// label (or id) looked for, with the nearest label that is defined.
+    NoSuchPos(String, Option<String>),
+    /// The row's kind has no counterpart in the new definition: the label
+    /// (or id) looked for, with the nearest label that is defined.
+    NoSuchKind(String, Option<String>),
+    /// The position is no longer separate, or has fewer players to separate.
+    InvalidRegion,
+    InvalidPosSuffix,
+    InvalidKindSuffix,
+    /// Another row was already moved into the same slot of an ordered position.
+    SlotTaken,
+    /// The rows moved to the same place hold more than `u32::MAX` pieces.
+    TooManyPieces,
+}
+
+/// A saved row that could not be upgraded.
+#[derive(Debug, PartialEq, Clone)]
+pub struct UnmappedRow {
+    pub row: ExportRow,
+    pub reason: MigrateError,
+}
+
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct Migration {
+    /// The upgraded rows, with rows that now coincide merged.
+    pub rows: Vec<ExportRow>,
+    pub unmapped: Vec<UnmappedRow>,
+}
+
+/// Where an old item ends up: the new label (or remapped id) and suffix,
+/// which is `None` if a suffix map moved it past the largest suffix.
+struct Target<'a> {
+    label: &'a str,
+    id: Option<u32>,
+    suffix: Option<Suffix>,
+}
+
+fn map_suffix<'a>(maps: &[&'a SuffixMapDef], label: &str, suffix: Suffix) -> Option<(Option<Suffix>, Option<&'a str>)> {
+    maps.iter()
+        .find(|m| m.label == label && m.from.min <= suffix.0 && suffix.0 <= m.from.max)
+        .map(|m| {
+            let mapped = m.to as i64 + suffix.0 as i64 - m.from.min as i64;
+            (i32::try_from(mapped).ok().map(Suffix), m.into.as_deref())
+        })
+}
+
+struct Migrator<'a> {
+    old: &'a GameSpec,
+    new: &'a GameSpec,
+    pos_renames: BTreeMap<&'a str, &'a str>,
+    kind_renames: BTreeMap<&'a str, &'a str>,
+    pos_remaps: BTreeMap<u32, u32>,
+    kind_remaps: BTreeMap<u32, u32>,
+    pos_suffix_maps: Vec<&'a SuffixMapDef>,
+    kind_suffix_maps: Vec<&'a SuffixMapDef>,
+}
+
+impl<'a> Migrator<'a> {
+    fn new(old: &'a GameSpec, new: &'a GameSpec) -> Migrator<'a> {
+        let mut migrator = Migrator {
+            old,
+            new,
+            pos_renames: BTreeMap::new(),
+            kind_renames: BTreeMap::new(),
+            pos_remaps: BTreeMap::new(),
+            kind_remaps: BTreeMap::new(),
+            pos_suffix_maps: Vec::new(),
+            kind_suffix_maps: Vec::new(),
+        };
+        for migration in &new.migrations {
+            match migration {
+                MigrationDef::RenamePos { from, to } => {
+                    migrator.pos_renames.insert(from, to);
+                }
+                MigrationDef::RenameKind { from, to } => {
+                    migrator.kind_renames.insert(from, to);
+                }
+                MigrationDef::RemapPos { from, to } => {
+                    migrator.pos_remaps.insert(*from, *to);
+                }
+                MigrationDef::RemapKind { from, to } => {
+                    migrator.kind_remaps.insert(*from, *to);
+                }
+                MigrationDef::MapPosSuffixes(map) => migrator.pos_suffix_maps.push(map),
+                MigrationDef::MapKindSuffixes(map) => migrator.kind_suffix_maps.push(map),
+            }
+        }
+        migrator
+    }
+
+    fn pos_target(&self, pos: &QPos) -> Option<Target<'a>> {
+        let old = self.old.pos_specs.find(&pos.pos)?;
+        let mut target = Target {
+            label: self.pos_renames.get(old.label.as_str()).copied().unwrap_or(&old.label),
+            id: self.pos_remaps.get(&pos.pos.as_u32()).copied(),
+            suffix: Some(pos.suffix),
+        };
+        if let Some((suffix, into)) = map_suffix(&self.pos_suffix_maps, &old.label, pos.suffix) {
+            target.suffix = suffix;
+            if let Some(into) = into {
+                target.label = into;
+                target.id = None;
+            }
+        }
+        Some(target)
+    }
+
+    fn kind_target(&self, kind: &QKind) -> Option<Target<'a>> {
+        let old = self.old.kind_specs.find(&kind.kind)?;
+        let mut target = Target {
+            label: self.kind_renames.get(old.label.as_str()).copied().unwrap_or(&old.label),
+            id: self.kind_remaps.get(&u32::from(kind.kind)).copied(),
+            suffix: Some(kind.suffix),
+        };
+        if let Some((suffix, into)) = map_suffix(&self.kind_suffix_maps, &old.label, kind.suffix) {
+            target.suffix = suffix;
+            if let Some(into) = into {
+                target.label = into;
+                target.id = None;
+            }
+        }
+        Some(target)
+    }
+
+    fn migrate_pos(&self, pos: &QPos) -> Result<QPos, MigrateError> {
+        let target = self.pos_target(pos)
+            .ok_or_else(|| MigrateError::NoSuchPos(pos.pos.as_u32().to_string(), None))?;
+        let spec = match target.id {
+            Some(id) => Pos::try_from(id).ok()
+                .and_then(|id| self.new.pos_specs.find(&id))
+                .ok_or_else(|| MigrateError::NoSuchPos(id.to_string(), None))?,
+            None => self.new.pos_specs.find_by_label(target.label).ok_or_else(|| {
+                let suggestion = self.new.suggest_pos_label(target.label).map(str::to_owned);
+                MigrateError::NoSuchPos(target.label.to_owned(), suggestion)
+            })?,
+        };
+
+        let region_valid = if spec.separate {
+            pos.region.0 < self.new.max_players() as u16
+        } else {
+            pos.region.0 == 0
+        };
+        if !region_valid {
+            return Err(MigrateError::InvalidRegion);
+        }
+        let suffix = target.suffix.ok_or(MigrateError::InvalidPosSuffix)?;
+        let suffix_valid = if spec.ordered {
+            suffix.0 >= 0
+        } else {
+            spec.suffixes.is_valid(suffix)
+        };
+        if !suffix_valid {
+            return Err(MigrateError::InvalidPosSuffix);
+        }
+        Ok(QPos { pos: spec.id, region: pos.region, suffix })
+    }
+
+    fn migrate_kind(&self, kind: &QKind) -> Result<QKind, MigrateError> {
+        let target = self.kind_target(kind)
+            .ok_or_else(|| MigrateError::NoSuchKind(u32::from(kind.kind).to_string(), None))?;
+        let spec = match target.id {
+            Some(id) => Kind::try_from(id).ok()
+                .and_then(|id| self.new.kind_specs.find(&id))
+                .ok_or_else(|| MigrateError::NoSuchKind(id.to_string(), None))?,
+            None => self.new.kind_specs.find_by_label(target.label).ok_or_else(|| {
+                let suggestion = self.new.suggest_kind_label(target.label).map(str::to_owned);
+                MigrateError::NoSuchKind(target.label.to_owned(), suggestion)
+            })?,
+        };
+
+        let suffix = target.suffix.filter(|s| spec.suffixes.is_valid(*s))
+            .ok_or(MigrateError::InvalidKindSuffix)?;
+        Ok(QKind { kind: spec.id, suffix })
+    }
+
+    fn is_ordered(&self, pos: &QPos) -> bool {
+        self.new.pos_specs.find(&pos.pos).is_some_and(|s| s.ordered)
+    }
+}
+
+/// Rewrites `rows`, saved under `old_spec`, for `new_spec` using the
+/// migrations of `new_spec`. Items without a migration keep their label.
+pub fn migrate(old_spec: &GameSpec, new_spec: &GameSpec, rows: &[ExportRow]) -> Migration {
+    let migrator = Migrator::new(old_spec, new_spec);
+
+    let mut migrated: BTreeMap<(QPos, QKind), u32> = BTreeMap::new();
+    let mut slots: BTreeSet<QPos> = BTreeSet::new();
+    let mut unmapped = Vec::new();
+    for row in rows {
+        let mapped = migrator.migrate_pos(&row.pos)
+            .and_then(|pos| migrator.migrate_kind(&row.kind).map(|kind| (pos, kind)));
+        let (pos, kind) = match mapped {
+            Ok(mapped) => mapped,
+            Err(reason) => {
+                unmapped.push(UnmappedRow { row: *row, reason });
+                continue;
+            }
+        };
+        if migrator.is_ordered(&pos) && !slots.insert(pos) {
+            unmapped.push(UnmappedRow { row: *row, reason: MigrateError::SlotTaken });
+            continue;
+        }
+        let count = migrated.entry((pos, kind)).or_insert(0);
+        match count.checked_add(row.count) {
+            Some(total) => *count = total,
+            None => unmapped.push(UnmappedRow { row: *row, reason: MigrateError::TooManyPieces }),
+        }
+    }
+
+    Migration {
+        rows: migrated.into_iter()
+            .map(|((pos, kind), count)| ExportRow { pos, kind, count })
+            .collect(),
+        unmapped,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::coords::Region;
+    use crate::defs::{GameDefBuilder, KindDef, PosDef, SuffixRangeDef};
+    use crate::error::Error;
+    use std::convert::TryInto;
+
+    fn old_spec() -> GameSpec {
+        GameDefBuilder::bld("whist")
+            .kind(KindDef::bld("card").suffix_range(1, 52))
+            .kind(KindDef::bld("leader"))
+            .pos(PosDef::bld("talon").hidden())
+            .pos(PosDef::bld("hand").hidden().separate())
+            .build()
+            .try_into()
+            .unwrap()
+    }
+
+    fn row(pos: u32, region: u16, kind: u32, suffix: i32, count: u32) -> ExportRow {
+        ExportRow {
+            pos: QPos { pos: pos.try_into().unwrap(), region: Region(region), suffix: Suffix(0) },
+            kind: QKind { kind: kind.try_into().unwrap(), suffix: Suffix(suffix) },
+            count,
+        }
+    }
+
+    #[test]
+    fn renames_and_splits_are_followed() {
+        let new_spec: GameSpec = GameDefBuilder::bld("whist")
+            .kind(KindDef::bld("leader"))
+            .kind(KindDef::bld("spade").suffix_range(1, 13))
+            .kind(KindDef::bld("heart").suffix_range(1, 13))
+            .pos(PosDef::bld("hand").hidden().separate())
+            .pos(PosDef::bld("deck").hidden())
+            .migration(MigrationDef::RenamePos { from: "talon".to_owned(), to: "deck".to_owned() })
+            .migration(MigrationDef::MapKindSuffixes(SuffixMapDef {
+                label: "card".to_owned(),
+                from: SuffixRangeDef { min: 1, max: 13 },
+                to: 1,
+                into: Some("spade".to_owned()),
+            }))
+            .migration(MigrationDef::MapKindSuffixes(SuffixMapDef {
+                label: "card".to_owned(),
+                from: SuffixRangeDef { min: 14, max: 26 },
+                to: 1,
+                into: Some("heart".to_owned()),
+            }))
+            .build()
+            .try_into()
+            .unwrap();
+
+        let rows = [row(1, 0, 1, 3, 1), row(2, 1, 1, 15, 1), row(2, 1, 2, 0, 1), row(1, 0, 1, 40, 1)];
+        let migration = migrate(&old_spec(), &new_spec, &rows);
+
+        assert_eq!(
+            vec![row(1, 1, 1, 0, 1), row(1, 1, 3, 2, 1), row(2, 0, 2, 3, 1)],
+            migration.rows
+        );
+        assert_eq!(
+            vec![UnmappedRow { row: rows[3], reason: MigrateError::NoSuchKind("card".to_owned(), None) }],
+            migration.unmapped
+        );
+    }
+
+    #[test]
+    fn unresolved_labels_come_with_suggestions() {
+        let new_spec: GameSpec = GameDefBuilder::bld("whist")
+            .kind(KindDef::bld("cards").suffix_range(1, 52))
+            .pos(PosDef::bld("deck").hidden())
+            .migration(MigrationDef::RenamePos { from: "talon".to_owned(), to: "dekc".to_owned() })
+            .build()
+            .try_into()
+            .unwrap();
+
+        let rows = [row(1, 0, 1, 3, 1), row(1, 0, 2, 0, 1)];
+        let migration = migrate(&old_spec(), &new_spec, &rows);
+
+        let reasons: Vec<MigrateError> = migration.unmapped.into_iter().map(|u| u.reason).collect();
+        assert_eq!(vec![MigrateError::NoSuchPos("dekc".to_owned(), Some("deck".to_owned())); 2], reasons);
+
+        let new_spec: GameSpec = GameDefBuilder::bld("whist")
+            .kind(KindDef::bld("cards").suffix_range(1, 52))
+            .pos(PosDef::bld("talon").hidden())
+            .build()
+            .try_into()
+            .unwrap();
+        let reasons: Vec<MigrateError> = migrate(&old_spec(), &new_spec, &rows).unmapped
+            .into_iter()
+            .map(|u| u.reason)
+            .collect();
+        assert_eq!(
+            vec![
+                MigrateError::NoSuchKind("card".to_owned(), Some("cards".to_owned())),
+                MigrateError::NoSuchKind("leader".to_owned(), None),
+            ],
+            reasons
+        );
+    }
+
+    #[test]
+    fn remapped_ids_take_precedence_over_labels() {
+        let new_spec: GameSpec = GameDefBuilder::bld("whist")
+            .kind(KindDef::bld("card").suffix_range(1, 52))
+            .kind(KindDef::bld("leader"))
+            .pos(PosDef::bld("hand").hidden().separate())
+            .pos(PosDef::bld("talon").hidden())
+            .pos(PosDef::bld("stock").hidden().id(7))
+            .migration(MigrationDef::RemapPos { from: 1, to: 7 })
+            .build()
+            .try_into()
+            .unwrap();
+
+        let rows = [row(1, 0, 1, 3, 2), row(2, 1, 1, 4, 1), row(2, 5, 1, 5, 1)];
+        let migration = migrate(&old_spec(), &new_spec, &rows);
+
+        assert_eq!(vec![row(1, 1, 1, 4, 1), row(7, 0, 1, 3, 2)], migration.rows);
+        assert_eq!(
+            vec![UnmappedRow { row: rows[2], reason: MigrateError::InvalidRegion }],
+            migration.unmapped
+        );
+    }
+
+    #[test]
+    fn merged_rows_that_overflow_are_unmapped() {
+        let new_spec: GameSpec = GameDefBuilder::bld("whist")
+            .kind(KindDef::bld("card").suffix_range(1, 52))
+            .pos(PosDef::bld("talon").hidden())
+            .migration(MigrationDef::MapKindSuffixes(SuffixMapDef {
+                label: "card".to_owned(),
+                from: SuffixRangeDef { min: 2, max: 2 },
+                to: 1,
+                into: None,
+            }))
+            .build()
+            .try_into()
+            .unwrap();
+
+        let rows = [row(1, 0, 1, 1, u32::MAX), row(1, 0, 1, 2, 1)];
+        let migration = migrate(&old_spec(), &new_spec, &rows);
+
+        assert_eq!(vec![row(1, 0, 1, 1, u32::MAX)], migration.rows);
+        assert_eq!(
+            vec![UnmappedRow { row: rows[1], reason: MigrateError::TooManyPieces }],
+            migration.unmapped
+        );
+    }
+
+    #[test]
+    fn suffix_maps_must_fit_the_suffixes() {
+        let maps = [
+            SuffixMapDef { label: "card".to_owned(), from: SuffixRangeDef { min: 13, max: 1 }, to: 1, into: None },
+            SuffixMapDef { label: "card".to_owned(), from: SuffixRangeDef { min: 1, max: 13 }, to: i32::MAX, into: None },
+            SuffixMapDef { label: "card".to_owned(), from: SuffixRangeDef { min: i32::MIN, max: 0 }, to: 0, into: None },
+        ];
+        for map in maps.iter() {
+            let result: Result<GameSpec, Error> = GameDefBuilder::bld("whist")
+                .migration(MigrationDef::MapKindSuffixes(map.clone()))
+                .build()
+                .try_into();
+            assert_eq!(Error::InvalidSuffixMap(map.clone()), result.unwrap_err());
+        }
+    }
+
+    #[test]
+    fn migrations_round_trip_through_yaml() {
+        let def = GameDefBuilder::bld("whist")
+            .migration(MigrationDef::RenameKind { from: "card".to_owned(), to: "tile".to_owned() })
+            .migration(MigrationDef::MapPosSuffixes(SuffixMapDef {
+                label: "hand".to_owned(),
+                from: SuffixRangeDef { min: 1, max: 4 },
+                to: 0,
+                into: None,
+            }))
+            .build();
+
+        let yaml = serde_yaml::to_string(&def).unwrap();
+        assert!(yaml.contains("rename_kind"), "{}", yaml);
+        assert_eq!(def, serde_yaml::from_str(&yaml).unwrap());
+    }
+}
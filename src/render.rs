@@ -0,0 +1,218 @@
+//! Plain text rendering of states, for tests, logs and the console.
+
+use std::collections::BTreeMap;
+use std::fmt;
+
+use crate::coords::{Pos, QKind, Region, Suffix};
+use crate::specs::GameSpec;
+use crate::state::{ExportRow, State};
+
+/// Lays `rows` out in columns under `headers`.
+pub fn render_table(headers: &[&str], rows: &[Vec<String>]) -> String {
+    let mut widths: Vec<usize> = headers.iter().map(|h| h.chars().count()).collect();
+    for row in rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+
+    let line = |cells: Vec<&str>| {
+        let cells: Vec<String> = cells.iter()
+            .zip(&widths)
+            .map(|(cell, width)| format!("{:<width$}", cell, width = width))
+            .collect();
+        cells.join(" | ").trim_end().to_owned()
+    };
+
+    let mut out = vec![
+        line(headers.to_vec()),
+        widths.iter().map(|w| "-".repeat(*w)).collect::<Vec<_>>().join("-+-"),
+    ];
+    for row in rows {
+        out.push(line(row.iter().map(String::as_str).collect()));
+    }
+    out.join("\n")
+}
+
+
+/// A state laid out as a table with a line per position and a column per
+/// region; see `State::display`.
+pub struct StateDisplay<'a> {
+    state: &'a State,
+    spec: &'a GameSpec,
+    locale: Option<&'a str>,
+}
+
+impl State {
+    /// Shows each position by label. Unordered pieces are listed as
+    /// `kind:suffix xN` and ordered ones as a sequence, bottom first.
+    pub fn display<'a>(&'a self, spec: &'a GameSpec) -> StateDisplay<'a> {
+        StateDisplay { state: self, spec, locale: None }
+    }
+}
+
+impl<'a> StateDisplay<'a> {
+    /// Shows names for `locale` in place of labels.
+    pub fn in_locale(mut self, locale: &'a str) -> Self {
+        self.locale = Some(locale);
+        self
+    }
+
+    fn describe_kind(&self, kind: &QKind) -> String {
+        match self.locale {
+            Some(locale) => self.spec.describe_kind_in(kind, locale),
+            None => self.spec.describe_kind(kind),
+        }
+    }
+
+    fn cell(&self, ordered: bool, rows: &[ExportRow]) -> String {
+        if ordered {
+            let kinds: Vec<String> = rows.iter().map(|r| self.describe_kind(&r.kind)).collect();
+            format!("[{}]", kinds.join(", "))
+        } else {
+            let kinds: Vec<String> = rows.iter()
+                .map(|r| format!("{} x{}", self.describe_kind(&r.kind), r.count))
+                .collect();
+            kinds.join(", ")
+        }
+    }
+}
+
+impl fmt::Display for StateDisplay<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut rows = self.state.export_rows();
+        rows.sort();
+
+        // Unordered positions get a line per suffix; for ordered ones the
+        // suffix is the slot, so all of a region's pieces share a line.
+        let mut lines: BTreeMap<(Pos, Suffix), BTreeMap<Region, Vec<ExportRow>>> = BTreeMap::new();
+        for pos_spec in &self.spec.pos_specs {
+            lines.entry((pos_spec.id, Suffix(0))).or_default();
+        }
+        let mut regions = 1;
+        for row in rows {
+            let ordered = self.spec.pos_specs.find(&row.pos.pos).is_some_and(|s| s.ordered);
+            let suffix = if ordered { Suffix(0) } else { row.pos.suffix };
+            regions = regions.max(row.pos.region.0 as usize + 1);
+            lines.entry((row.pos.pos, suffix))
+                .or_default()
+                .entry(row.pos.region)
+                .or_default()
+                .push(row);
+        }
+        if self.spec.pos_specs.into_iter().any(|s| s.separate) {
+            regions = regions.max(self.spec.max_players() as usize);
+        }
+
+        let mut cells = Vec::with_capacity(lines.len());
+        for ((pos, suffix), by_region) in &lines {
+            let pos_spec = self.spec.pos_specs.find(pos);
+            let mut label = match (pos_spec, self.locale) {
+                (Some(spec), Some(locale)) => spec.display_name(locale).to_owned(),
+                (Some(spec), None) => spec.label.clone(),
+                (None, _) => format!("{:?}", pos),
+            };
+            if suffix.0 != 0 {
+                let suffix_label = pos_spec.and_then(|s| match self.locale {
+                    Some(locale) => s.suffixes.display_name(*suffix, locale),
+                    None => s.suffixes.find_label(*suffix),
+                });
+                match suffix_label {
+                    Some(suffix) => label.push_str(&format!(":{}", suffix)),
+                    None => label.push_str(&format!(":{}", suffix.0)),
+                }
+            }
+            let ordered = pos_spec.is_some_and(|s| s.ordered);
+            let mut line = vec![label];
+            line.extend((0..regions).map(|region| {
+                by_region.get(&Region(region as u16))
+                    .map(|rows| self.cell(ordered, rows))
+                    .unwrap_or_default()
+            }));
+            cells.push(line);
+        }
+
+        let headers: Vec<String> = (0..regions).map(|r| format!("region {}", r)).collect();
+        let mut header_refs = vec!["pos"];
+        header_refs.extend(headers.iter().map(String::as_str));
+        write!(f, "{}", render_table(&header_refs, &cells))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::coords::QPos;
+    use crate::defs::{GameDefBuilder, KindDef, PosDef, SuffixDef};
+    use crate::diff::Diff;
+    use std::convert::TryInto;
+
+    #[test]
+    fn shows_regions_as_columns() {
+        let spec: GameSpec = GameDefBuilder::bld("whist")
+            .max_players(3)
+            .kind(KindDef::bld("card").suffix_range(1, 52))
+            .kind(KindDef::bld("suit").suffix(SuffixDef::bld("hearts")))
+            .pos(PosDef::bld("deck").ordered())
+            .pos(PosDef::bld("hand").separate())
+            .pos(PosDef::bld("trump"))
+            .build()
+            .try_into()
+            .unwrap();
+
+        let row = |pos: u32, region: u16, slot: i32, kind: u32, suffix: i32, count: u32| ExportRow {
+            pos: QPos { pos: pos.try_into().unwrap(), region: Region(region), suffix: Suffix(slot) },
+            kind: QKind { kind: kind.try_into().unwrap(), suffix: Suffix(suffix) },
+            count,
+        };
+        let mut state = State::new(&spec);
+        state.apply_diff(&Diff::between(Vec::new(), vec![
+            row(1, 0, 0, 1, 9, 1),
+            row(1, 0, 1, 1, 3, 1),
+            row(2, 0, 0, 1, 4, 1),
+            row(2, 0, 0, 1, 5, 1),
+            row(2, 2, 0, 1, 6, 1),
+            row(3, 0, 0, 2, 1, 2),
+        ])).unwrap();
+
+        assert_eq!(
+            "\
+pos   | region 0             | region 1 | region 2
+------+----------------------+----------+----------
+deck  | [card:9, card:3]     |          |
+hand  | card:4 x1, card:5 x1 |          | card:6 x1
+trump | suit:hearts x2       |          |",
+            state.display(&spec).to_string()
+        );
+    }
+
+    #[test]
+    fn shows_names_for_locale() {
+        let spec: GameSpec = GameDefBuilder::bld("whist")
+            .kind(KindDef::bld("card").suffix_range(1, 52))
+            .kind(KindDef::bld("suit").name("fr", "couleur").suffix(SuffixDef::bld("hearts").name("fr", "Cœurs")))
+            .pos(PosDef::bld("trump").name("fr", "atout").name("fr-CA", "atout!"))
+            .build()
+            .try_into()
+            .unwrap();
+
+        let mut state = State::new(&spec);
+        state.apply_diff(&Diff::between(Vec::new(), vec![
+            ExportRow {
+                pos: QPos { pos: 1.try_into().unwrap(), region: Region(0), suffix: Suffix(0) },
+                kind: QKind { kind: 2.try_into().unwrap(), suffix: Suffix(1) },
+                count: 1,
+            },
+        ])).unwrap();
+
+        assert_eq!(
+            "\
+pos   | region 0
+------+-----------------
+atout | couleur:Cœurs x1",
+            state.display(&spec).in_locale("fr-FR").to_string()
+        );
+        assert!(state.display(&spec).in_locale("fr-CA").to_string().contains("atout!"));
+        assert!(state.display(&spec).in_locale("de").to_string().contains("trump | suit:hearts x1"));
+    }
+}
@@ -0,0 +1,281 @@
+//! A JSON Schema for game definition files, for editors and other tooling
+//! that want to validate or complete them.
+//!
+//! The schema is kept by hand next to `defs`; a field added there needs an
+//! entry here too, which the tests check.
+
+use serde_json::{json, Value};
+
+use crate::coords::{MAX_KIND, MAX_POS, MIN_KIND, MIN_POS};
+
+/// An id that may be left out, in which case `knott fmt` assigns one.
+fn id(min: u32, max: Option<u32>) -> Value {
+    let mut schema = json!({
+        "type": "integer",
+        "minimum": min,
+        "description": "Left out, the id is assigned by `knott fmt`."
+    });
+    if let Some(max) = max {
+        schema["maximum"] = json!(max);
+    }
+    schema
+}
+
+fn aliases() -> Value {
+    json!({
+        "type": "array",
+        "items": { "type": "string" },
+        "uniqueItems": true,
+        "description": "Other labels the item can be found by."
+    })
+}
+
+fn names() -> Value {
+    json!({
+        "type": "object",
+        "additionalProperties": { "type": "string" },
+        "description": "Names to show players, by locale such as `fr` or `pt-BR`."
+    })
+}
+
+fn attributes() -> Value {
+    json!({
+        "type": "object",
+        "additionalProperties": { "type": ["integer", "string", "boolean"] },
+        "description": "Values such as a card's points or colour, by name."
+    })
+}
+
+fn flag(description: &str) -> Value {
+    json!({ "type": "boolean", "default": false, "description": description })
+}
+
+fn suffix_range_def() -> Value {
+    json!({
+        "type": "object",
+        "description": "Suffixes are every number from min to max inclusive.",
+        "properties": {
+            "min": { "type": "integer" },
+            "max": { "type": "integer" }
+        },
+        "required": ["min", "max"],
+        "additionalProperties": false
+    })
+}
+
+fn suffix_def() -> Value {
+    json!({
+        "type": "object",
+        "properties": {
+            "label": { "type": "string" },
+            "id": id(0, None),
+            "aliases": aliases(),
+            "names": names(),
+            "attributes": attributes()
+        },
+        "required": ["label"],
+        "additionalProperties": false
+    })
+}
+
+fn dimension_def() -> Value {
+    json!({
+        "type": "object",
+        "properties": {
+            "label": { "type": "string" },
+            "values": { "type": "array", "items": { "type": "string" }, "minItems": 1, "uniqueItems": true }
+        },
+        "required": ["label", "values"],
+        "additionalProperties": false
+    })
+}
+
+fn suffix_product_def() -> Value {
+    json!({
+        "type": "object",
+        "description": "A suffix for every combination of values, labelled like `hearts-queen`.",
+        "properties": {
+            "dimensions": { "type": "array", "items": { "$ref": "#/definitions/DimensionDef" }, "minItems": 1 }
+        },
+        "required": ["dimensions"],
+        "additionalProperties": false
+    })
+}
+
+fn kind_def() -> Value {
+    json!({
+        "type": "object",
+        "properties": {
+            "label": { "type": "string" },
+            "id": id(MIN_KIND, Some(MAX_KIND)),
+            "aliases": aliases(),
+            "names": names(),
+            "attributes": attributes(),
+            "suffix_range": { "$ref": "#/definitions/SuffixRangeDef" },
+            "suffixes": { "type": "array", "items": { "$ref": "#/definitions/SuffixDef" } },
+            "suffix_product": { "$ref": "#/definitions/SuffixProductDef" }
+        },
+        "required": ["label"],
+        "allOf": [
+            { "not": { "required": ["suffix_range", "suffixes"] } },
+            { "not": { "required": ["suffix_range", "suffix_product"] } },
+            { "not": { "required": ["suffixes", "suffix_product"] } }
+        ],
+        "additionalProperties": false
+    })
+}
+
+fn pos_def() -> Value {
+    json!({
+        "type": "object",
+        "properties": {
+            "label": { "type": "string" },
+            "id": id(MIN_POS, Some(MAX_POS)),
+            "aliases": aliases(),
+            "names": names(),
+            "suffix_range": { "$ref": "#/definitions/SuffixRangeDef" },
+            "suffixes": { "type": "array", "items": { "$ref": "#/definitions/SuffixDef" } },
+            "separate": flag("Each player has their own region of the position."),
+            "ordered": flag("Pieces are kept in numbered slots, in order."),
+            "hidden": flag("Players can not see the pieces, except in their own region.")
+        },
+        "required": ["label"],
+        "not": { "required": ["suffix_range", "suffixes"] },
+        "additionalProperties": false
+    })
+}
+
+fn suffix_map_def() -> Value {
+    json!({
+        "type": "object",
+        "properties": {
+            "label": { "type": "string" },
+            "from": { "$ref": "#/definitions/SuffixRangeDef" },
+            "to": { "type": "integer" },
+            "into": { "type": "string" }
+        },
+        "required": ["label", "from", "to"],
+        "additionalProperties": false
+    })
+}
+
+/// A migration is an object with a single key naming the step.
+fn migration_def() -> Value {
+    let step = |name: &str, body: Value| {
+        json!({
+            "type": "object",
+            "properties": { name: body },
+            "required": [name],
+            "additionalProperties": false
+        })
+    };
+    let rename = json!({
+        "type": "object",
+        "properties": { "from": { "type": "string" }, "to": { "type": "string" } },
+        "required": ["from", "to"],
+        "additionalProperties": false
+    });
+    let remap = json!({
+        "type": "object",
+        "properties": { "from": { "type": "integer" }, "to": { "type": "integer" } },
+        "required": ["from", "to"],
+        "additionalProperties": false
+    });
+    let map = json!({ "$ref": "#/definitions/SuffixMapDef" });
+    json!({
+        "oneOf": [
+            step("rename_pos", rename.clone()),
+            step("rename_kind", rename),
+            step("remap_pos", remap.clone()),
+            step("remap_kind", remap),
+            step("map_pos_suffixes", map.clone()),
+            step("map_kind_suffixes", map)
+        ]
+    })
+}
+
+/// The JSON Schema (draft 7) of a `GameDef`.
+pub fn game_def_schema() -> Value {
+    json!({
+        "$schema": "http://json-schema.org/draft-07/schema#",
+        "title": "GameDef",
+        "type": "object",
+        "properties": {
+            "label": { "type": "string" },
+            "min_players": { "type": "integer", "minimum": 0, "maximum": 255 },
+            "max_players": { "type": "integer", "minimum": 0, "maximum": 255 },
+            "kind_defs": { "type": "array", "items": { "$ref": "#/definitions/KindDef" } },
+            "pos_defs": { "type": "array", "items": { "$ref": "#/definitions/PosDef" } },
+            "migrations": { "type": "array", "items": { "$ref": "#/definitions/MigrationDef" } }
+        },
+        "required": ["label", "min_players", "max_players", "kind_defs", "pos_defs"],
+        "additionalProperties": false,
+        "definitions": {
+            "KindDef": kind_def(),
+            "PosDef": pos_def(),
+            "SuffixDef": suffix_def(),
+            "SuffixRangeDef": suffix_range_def(),
+            "SuffixProductDef": suffix_product_def(),
+            "DimensionDef": dimension_def(),
+            "SuffixMapDef": suffix_map_def(),
+            "MigrationDef": migration_def()
+        }
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::defs::{GameDefBuilder, KindDef, MigrationDef, PosDef, SuffixDef, SuffixMapDef, SuffixRangeDef};
+
+    /// Checks every field serialized in `value` is a property of `schema`.
+    fn assert_described(schema: &Value, value: &Value) {
+        for field in value.as_object().unwrap().keys() {
+            assert!(schema["properties"].get(field).is_some(), "{} is not in the schema", field);
+        }
+    }
+
+    #[test]
+    fn schema_describes_every_field() {
+        let def = GameDefBuilder::bld("whist")
+            .kind(KindDef::bld("card").alias("cards").attribute("points", 1).suffix_range(1, 52))
+            .kind(
+                KindDef::bld("suit")
+                    .name("fr", "couleur")
+                    .suffix(SuffixDef::bld("hearts").alias("♥").name("fr", "Cœurs").attribute("red", true)),
+            )
+            .kind(KindDef::bld("deck").dimension("suit", &["hearts", "spades"]).dimension("rank", &["ace", "king"]))
+            .pos(PosDef::bld("hand").alias("hands").name("fr", "main").hidden().separate().ordered().suffix_range(0, 12))
+            .migration(MigrationDef::MapKindSuffixes(SuffixMapDef {
+                label: "card".to_owned(),
+                from: SuffixRangeDef { min: 1, max: 13 },
+                to: 1,
+                into: Some("spade".to_owned()),
+            }))
+            .build();
+        let value = serde_json::to_value(&def).unwrap();
+        let schema = game_def_schema();
+        let definitions = &schema["definitions"];
+
+        assert_described(&schema, &value);
+        for kind in value["kind_defs"].as_array().unwrap() {
+            assert_described(&definitions["KindDef"], kind);
+        }
+        assert_described(&definitions["PosDef"], &value["pos_defs"][0]);
+        assert_described(&definitions["SuffixProductDef"], &value["kind_defs"][2]["suffix_product"]);
+        assert_described(&definitions["DimensionDef"], &value["kind_defs"][2]["suffix_product"]["dimensions"][0]);
+        assert_described(&definitions["SuffixDef"], &value["kind_defs"][1]["suffixes"][0]);
+        assert_described(&definitions["SuffixRangeDef"], &value["kind_defs"][0]["suffix_range"]);
+        assert_described(&definitions["SuffixMapDef"], &value["migrations"][0]["map_kind_suffixes"]);
+    }
+
+    #[test]
+    fn schema_has_defaults_and_id_ranges() {
+        let schema = game_def_schema();
+        let pos = &schema["definitions"]["PosDef"]["properties"];
+        assert_eq!(json!(false), pos["hidden"]["default"]);
+        assert_eq!(json!(9999), pos["id"]["maximum"]);
+        assert_eq!(json!(1), schema["definitions"]["KindDef"]["properties"]["id"]["minimum"]);
+        assert_eq!(json!(0), schema["definitions"]["SuffixDef"]["properties"]["id"]["minimum"]);
+    }
+}
@@ -0,0 +1,465 @@
+//! A game server speaking a line based JSON protocol over TCP.
+//!
+//! Each line a client sends is a `Request` and each line it receives is a
+//! `Response`. Once a client has joined a game as a player it is sent the
+//! part of every committed diff that player is allowed to see.
+
+use std::collections::HashMap;
+use std::io;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::mpsc::{channel, Sender};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::thread;
+
+use serde::{Deserialize, Serialize};
+
+use crate::diff::Diff;
+use crate::coords::{QKind, QPos};
+use crate::specs::{GameSpec, PlayerNum};
+use crate::state::{Cmd, CmdError, ExportRow, State};
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Request {
+    Create,
+    Join { game: u32, player: PlayerNum },
+    Apply { cmds: Vec<Cmd> },
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Response {
+    Created { game: u32 },
+    Joined { game: u32, player: PlayerNum, rows: Vec<ExportRow> },
+    Applied,
+    Diff { game: u32, diff: Diff },
+    Error { message: String },
+}
+
+struct Client {
+    connection: usize,
+    player: PlayerNum,
+    sender: Sender<Response>,
+}
+
+struct Game {
+    state: State,
+    clients: Arc<Mutex<Vec<Client>>>,
+}
+
+struct Lobby {
+    spec: Arc<GameSpec>,
+    games: HashMap<u32, Game>,
+    next_game: u32,
+}
+
+impl Lobby {
+    fn create(&mut self) -> u32 {
+        let id = self.next_game;
+        self.next_game += 1;
+
+        let clients: Arc<Mutex<Vec<Client>>> = Arc::new(Mutex::new(Vec::new()));
+        let mut state = State::new(&self.spec);
+        let spec = self.spec.clone();
+        let subscribers = clients.clone();
+        state.subscribe(move |diff: &Diff| {
+            lock(&subscribers).retain(|client| {
+                let diff = diff.for_player(&spec, client.player);
+                diff.is_empty() || client.sender.send(Response::Diff { game: id, diff }).is_ok()
+            });
+        });
+
+        self.games.insert(id, Game { state, clients });
+        id
+    }
+
+    /// Subscribes `connection` to `game` as `player`, replacing any game it
+    /// joined before.
+    fn join(&mut self, game: u32, player: PlayerNum, connection: usize, sender: Sender<Response>) -> Response {
+        if player.0 >= self.spec.max_players() {
+            return error(format!("no such player {}", player.0));
+        }
+        if !self.games.contains_key(&game) {
+            return error(format!("no such game {}", game));
+        }
+        for g in self.games.values() {
+            lock(&g.clients).retain(|client| client.connection != connection);
+        }
+        let g = &self.games[&game];
+        lock(&g.clients).push(Client { connection, player, sender });
+        let rows = g.state.export_player_rows(&self.spec, player);
+        Response::Joined { game, player, rows }
+    }
+
+    /// Applies `cmds` for `player` in one transaction; if any fails none of
+    /// them apply.
+    fn apply(&mut self, game: u32, player: PlayerNum, cmds: &[Cmd]) -> Response {
+        let state = match self.games.get_mut(&game) {
+            Some(g) => &mut g.state,
+            None => return error(format!("no such game {}", game)),
+        };
+        for (index, cmd) in cmds.iter().enumerate() {
+            if let Err(message) = check_cmd(&self.spec, player, cmd) {
+                return error(format!("command {}: {}", index + 1, message));
+            }
+        }
+        let mut tx = state.start_tx();
+        for (index, cmd) in cmds.iter().enumerate() {
+            if let Err(e) = tx.apply(cmd) {
+                tx.rollback();
+                return error(format!("command {}: {}", index + 1, describe_cmd_error(&self.spec, player, &e)));
+            }
+        }
+        tx.commit();
+        Response::Applied
+    }
+}
+
+fn error(message: String) -> Response {
+    Response::Error { message }
+}
+
+/// Locks `mutex` even if another client's thread panicked while holding it,
+/// rather than failing every later request of every client.
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+fn check_pos(spec: &GameSpec, pos: &QPos) -> Result<(), String> {
+    let pos_spec = spec.find_pos(pos.pos).ok_or_else(|| format!("no such position {}", pos.pos.as_u32()))?;
+    let regions = if pos_spec.is_separate() { spec.max_players() as u16 } else { 1 };
+    if pos.region.0 >= regions {
+        return Err(format!("no region {} of position '{}'", pos.region.0, pos_spec.label));
+    }
+    // The suffix of an ordered position is its slot, which the state checks.
+    if !pos_spec.is_ordered() && !pos_spec.suffixes().is_valid(pos.suffix) {
+        return Err(format!("no suffix {} of position '{}'", pos.suffix.0, pos_spec.label));
+    }
+    Ok(())
+}
+
+fn check_kind(spec: &GameSpec, kind: &QKind) -> Result<(), String> {
+    let kind_spec = spec.find_kind(kind.kind).ok_or_else(|| format!("no such kind {}", kind.kind.as_u32()))?;
+    if !kind_spec.suffixes.is_valid(kind.suffix) {
+        return Err(format!("no suffix {} of kind '{}'", kind.suffix.0, kind_spec.label));
+    }
+    Ok(())
+}
+
+/// Checks that `cmd` names things the spec defines, and that it does not
+/// take pieces from a region hidden from `player`.
+fn check_cmd(spec: &GameSpec, player: PlayerNum, cmd: &Cmd) -> Result<(), String> {
+    match cmd {
+        Cmd::CreatePieces(cmd) => {
+            check_pos(spec, &cmd.pos)?;
+            check_kind(spec, &cmd.kind)
+        }
+        Cmd::MovePieces(cmd) => {
+            check_pos(spec, &cmd.from)?;
+            check_pos(spec, &cmd.to)?;
+            check_kind(spec, &cmd.kind)?;
+            let from = spec.find_pos(cmd.from.pos).filter(|s| s.is_separate() && s.is_hidden());
+            if from.is_some() && cmd.from.region != player.region() {
+                return Err(format!("cannot take pieces from {}", spec.describe_pos(&cmd.from)));
+            }
+            Ok(())
+        }
+    }
+}
+
+/// Describes why a command failed without giving away what is in positions
+/// `player` cannot see.
+fn describe_cmd_error(spec: &GameSpec, player: PlayerNum, e: &CmdError) -> String {
+    match e {
+        CmdError::NoSuchPos(pos) => format!("no such position {}", pos.as_u32()),
+        CmdError::InvalidSlot(pos) | CmdError::NotEnoughPieces(pos, _) | CmdError::TooManyPieces(pos, _)
+            if !spec.is_visible(pos, player) =>
+        {
+            format!("cannot apply at {}", spec.describe_pos(pos))
+        }
+        CmdError::InvalidSlot(pos) => format!("no slot {}", spec.describe_pos(pos)),
+        CmdError::NotEnoughPieces(pos, kind) => {
+            format!("not enough {} at {}", spec.describe_kind(kind), spec.describe_pos(pos))
+        }
+        CmdError::TooManyPieces(pos, kind) => {
+            format!("too many {} at {}", spec.describe_kind(kind), spec.describe_pos(pos))
+        }
+    }
+}
+
+/// Accepts clients on `listener` until it fails, hosting games of `spec`.
+pub fn serve(spec: GameSpec, listener: TcpListener) -> io::Result<()> {
+    let lobby = Arc::new(Mutex::new(Lobby {
+        spec: Arc::new(spec),
+        games: HashMap::new(),
+        next_game: 1,
+    }));
+
+    for (connection, stream) in listener.incoming().enumerate() {
+        let stream = stream?;
+        let lobby = lobby.clone();
+        thread::spawn(move || handle_client(lobby, connection, stream));
+    }
+    Ok(())
+}
+
+fn handle_client(lobby: Arc<Mutex<Lobby>>, connection: usize, stream: TcpStream) -> io::Result<()> {
+    let (sender, receiver) = channel::<Response>();
+
+    let mut writer = stream.try_clone()?;
+    thread::spawn(move || -> io::Result<()> {
+        for response in receiver {
+            let line = serde_json::to_string(&response)?;
+            writeln!(writer, "{}", line)?;
+        }
+        Ok(())
+    });
+
+    let mut joined: Option<(u32, PlayerNum)> = None;
+    for line in BufReader::new(stream).lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let response = match serde_json::from_str::<Request>(&line) {
+            Ok(Request::Create) => Response::Created { game: lock(&lobby).create() },
+            Ok(Request::Join { game, player }) => {
+                let response = lock(&lobby).join(game, player, connection, sender.clone());
+                if let Response::Joined { .. } = response {
+                    joined = Some((game, player));
+                }
+                response
+            }
+            Ok(Request::Apply { cmds }) => match joined {
+                Some((game, player)) => lock(&lobby).apply(game, player, &cmds),
+                None => error("join a game before applying commands".to_owned()),
+            },
+            Err(e) => error(e.to_string()),
+        };
+        if sender.send(response).is_err() {
+            break;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::coords::{QKind, QPos, Region, Suffix};
+    use crate::defs::{GameDefBuilder, KindDef, PosDef};
+    use crate::state::{CreatePieces, MovePieces};
+    use std::convert::TryInto;
+    use std::io::Lines;
+
+    struct TestClient {
+        stream: TcpStream,
+        lines: Lines<BufReader<TcpStream>>,
+    }
+
+    impl TestClient {
+        fn connect(addr: std::net::SocketAddr) -> TestClient {
+            let stream = TcpStream::connect(addr).unwrap();
+            let lines = BufReader::new(stream.try_clone().unwrap()).lines();
+            TestClient { stream, lines }
+        }
+
+        fn send(&mut self, request: &Request) -> Response {
+            writeln!(self.stream, "{}", serde_json::to_string(request).unwrap()).unwrap();
+            self.receive()
+        }
+
+        fn receive(&mut self) -> Response {
+            let line = self.lines.next().unwrap().unwrap();
+            serde_json::from_str(&line).unwrap()
+        }
+    }
+
+    fn create_pieces(pos: u32, region: u16, card: i32) -> Cmd {
+        Cmd::CreatePieces(CreatePieces {
+            pos: QPos { pos: pos.try_into().unwrap(), region: Region(region), suffix: Suffix(0) },
+            kind: QKind { kind: 1.try_into().unwrap(), suffix: Suffix(card) },
+            count: 1,
+        })
+    }
+
+    #[test]
+    fn broadcasts_redacted_diffs_to_players() {
+        let spec: GameSpec = GameDefBuilder::bld("whist")
+            .kind(KindDef::bld("card").suffix_range(1, 52))
+            .pos(PosDef::bld("hand").hidden().separate())
+            .pos(PosDef::bld("trick"))
+            .build()
+            .try_into()
+            .unwrap();
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        thread::spawn(move || serve(spec, listener));
+
+        let mut alice = TestClient::connect(addr);
+        let mut bob = TestClient::connect(addr);
+
+        assert_eq!(Response::Created { game: 1 }, alice.send(&Request::Create));
+        assert_eq!(
+            Response::Joined { game: 1, player: PlayerNum(0), rows: vec![] },
+            alice.send(&Request::Join { game: 1, player: PlayerNum(0) })
+        );
+        assert_eq!(
+            Response::Joined { game: 1, player: PlayerNum(1), rows: vec![] },
+            bob.send(&Request::Join { game: 1, player: PlayerNum(1) })
+        );
+
+        let in_hand = create_pieces(1, 0, 5);
+        let in_trick = create_pieces(2, 0, 9);
+
+        match alice.send(&Request::Apply { cmds: vec![in_hand, in_trick] }) {
+            Response::Diff { diff, .. } => assert_eq!(2, diff.iter().count()),
+            other => panic!("unexpected {:?}", other),
+        }
+        assert_eq!(Response::Applied, alice.receive());
+
+        match bob.receive() {
+            Response::Diff { diff, .. } => assert_eq!(1, diff.iter().count()),
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    fn whist() -> GameSpec {
+        GameDefBuilder::bld("whist")
+            .kind(KindDef::bld("card").suffix_range(1, 52))
+            .pos(PosDef::bld("trick"))
+            .build()
+            .try_into()
+            .unwrap()
+    }
+
+    #[test]
+    fn failed_requests_change_nothing() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        thread::spawn(move || serve(whist(), listener));
+
+        let mut alice = TestClient::connect(addr);
+        let mut bob = TestClient::connect(addr);
+        alice.send(&Request::Create);
+        alice.send(&Request::Join { game: 1, player: PlayerNum(0) });
+        bob.send(&Request::Join { game: 1, player: PlayerNum(1) });
+
+        let valid = create_pieces(1, 0, 5);
+        let invalid = create_pieces(9, 0, 5);
+        match alice.send(&Request::Apply { cmds: vec![valid, invalid] }) {
+            Response::Error { .. } => {}
+            other => panic!("unexpected {:?}", other),
+        }
+
+        assert_eq!(
+            Response::Joined { game: 1, player: PlayerNum(1), rows: vec![] },
+            bob.send(&Request::Join { game: 1, player: PlayerNum(1) })
+        );
+    }
+
+    #[test]
+    fn rejects_commands_outside_the_spec_or_the_players_view() {
+        let spec: GameSpec = GameDefBuilder::bld("whist")
+            .kind(KindDef::bld("card").suffix_range(1, 52))
+            .pos(PosDef::bld("hand").hidden().separate())
+            .pos(PosDef::bld("trick"))
+            .build()
+            .try_into()
+            .unwrap();
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        thread::spawn(move || serve(spec, listener));
+
+        let mut alice = TestClient::connect(addr);
+        let mut bob = TestClient::connect(addr);
+        alice.send(&Request::Create);
+        alice.send(&Request::Join { game: 1, player: PlayerNum(0) });
+        bob.send(&Request::Join { game: 1, player: PlayerNum(1) });
+        bob.send(&Request::Apply { cmds: vec![create_pieces(1, 1, 5)] });
+        assert_eq!(Response::Applied, bob.receive());
+
+        let unknown_kind = Cmd::CreatePieces(CreatePieces {
+            pos: QPos { pos: 2.try_into().unwrap(), region: Region(0), suffix: Suffix(0) },
+            kind: QKind { kind: 2.try_into().unwrap(), suffix: Suffix(0) },
+            count: 1,
+        });
+        let peek = Cmd::MovePieces(MovePieces {
+            from: QPos { pos: 1.try_into().unwrap(), region: Region(1), suffix: Suffix(0) },
+            to: QPos { pos: 2.try_into().unwrap(), region: Region(0), suffix: Suffix(0) },
+            kind: QKind { kind: 1.try_into().unwrap(), suffix: Suffix(5) },
+            count: 1,
+        });
+        let cases = vec![
+            (create_pieces(1, 2, 5), "command 1: no region 2 of position 'hand'"),
+            (create_pieces(2, 1, 5), "command 1: no region 1 of position 'trick'"),
+            (create_pieces(2, 0, 53), "command 1: no suffix 53 of kind 'card'"),
+            (unknown_kind, "command 1: no such kind 2"),
+            (peek, "command 1: cannot take pieces from hand[1]"),
+        ];
+        for (cmd, expected) in cases {
+            match alice.send(&Request::Apply { cmds: vec![cmd] }) {
+                Response::Error { message } => assert_eq!(expected, message),
+                other => panic!("unexpected {:?}", other),
+            }
+        }
+
+        let take = Cmd::MovePieces(MovePieces {
+            from: QPos { pos: 1.try_into().unwrap(), region: Region(0), suffix: Suffix(0) },
+            to: QPos { pos: 2.try_into().unwrap(), region: Region(0), suffix: Suffix(0) },
+            kind: QKind { kind: 1.try_into().unwrap(), suffix: Suffix(5) },
+            count: 1,
+        });
+        match alice.send(&Request::Apply { cmds: vec![take] }) {
+            Response::Error { message } => assert_eq!("command 1: not enough card:5 at hand[0]", message),
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn rejoining_replaces_the_subscription() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        thread::spawn(move || serve(whist(), listener));
+
+        let mut alice = TestClient::connect(addr);
+        let mut bob = TestClient::connect(addr);
+        alice.send(&Request::Create);
+        alice.send(&Request::Join { game: 1, player: PlayerNum(0) });
+        bob.send(&Request::Join { game: 1, player: PlayerNum(0) });
+        bob.send(&Request::Join { game: 1, player: PlayerNum(1) });
+        match bob.send(&Request::Join { game: 1, player: PlayerNum(2) }) {
+            Response::Error { message } => assert_eq!("no such player 2", message),
+            other => panic!("unexpected {:?}", other),
+        }
+
+        alice.send(&Request::Apply { cmds: vec![create_pieces(1, 0, 5)] });
+        match bob.receive() {
+            Response::Diff { diff, .. } => assert_eq!(1, diff.iter().count()),
+            other => panic!("unexpected {:?}", other),
+        }
+        assert_eq!(Response::Applied, bob.send(&Request::Apply { cmds: vec![] }));
+    }
+
+    #[test]
+    fn reports_bad_requests() {
+        let spec: GameSpec = GameDefBuilder::bld("whist").build().try_into().unwrap();
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        thread::spawn(move || serve(spec, listener));
+
+        let mut client = TestClient::connect(addr);
+        match client.send(&Request::Apply { cmds: vec![] }) {
+            Response::Error { .. } => {}
+            other => panic!("unexpected {:?}", other),
+        }
+        match client.send(&Request::Join { game: 7, player: PlayerNum(0) }) {
+            Response::Error { message } => assert_eq!("no such game 7", message),
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+}
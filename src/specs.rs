@@ -1,28 +1,71 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::convert::{TryFrom, TryInto};
 use std::fmt;
 
-use crate::coords::{Kind, Pos, InvalidKind, Suffix, InvalidPos};
-use crate::defs::{GameDef, KindDef, SuffixDef, PosDef, SuffixRangeDef};
+use crate::coords::{Kind, Pos, InvalidKind, Suffix, InvalidPos, Region, QPos, QKind};
+use crate::defs::{
+    AttributeValue, DimensionDef, GameDef, KindDef, MigrationDef, PosDef, SuffixDef, SuffixMapDef, SuffixProductDef,
+    SuffixRangeDef,
+};
 use crate::error::{Error, ItemError, SuffixRowError};
-use crate::lookup::{LookupTable, HasId, Labelled};
+use crate::lookup::{LookupTable, HasId, Labelled, LabelCase, Relabel};
+
+/// The name of an item for `locale`: the name for the locale itself, such
+/// as `fr-CA`, then for its language, `fr`, and otherwise the label.
+fn display_name<'a>(label: &'a str, names: &'a BTreeMap<String, String>, locale: &str) -> &'a str {
+    let language = locale.split(['-', '_']).next().unwrap_or(locale);
+    names.get(locale)
+        .or_else(|| names.get(language))
+        .map(String::as_str)
+        .unwrap_or(label)
+}
 
 
 #[derive(Debug)]
 pub struct KindSpec {
     pub label: String,
     pub id: Kind,
+    pub aliases: Vec<String>,
+    pub names: BTreeMap<String, String>,
+    pub attributes: BTreeMap<String, AttributeValue>,
     pub suffixes: SuffixSpec,
 }
 
+impl KindSpec {
+    pub fn display_name(&self, locale: &str) -> &str {
+        display_name(&self.label, &self.names, locale)
+    }
+
+    /// The attribute `name` of pieces of this kind with `suffix`: the
+    /// suffix's own value if it has one, otherwise the kind's.
+    pub fn attribute(&self, suffix: Suffix, name: &str) -> Option<&AttributeValue> {
+        self.suffixes.table()
+            .and_then(|table| table.find(&suffix))
+            .and_then(|r| r.attributes.get(name))
+            .or_else(|| self.attributes.get(name))
+    }
+}
+
 impl HasId<Kind> for KindSpec {
     fn id(&self) -> Kind {
         self.id
     }
 }
+impl Relabel for KindSpec {
+    fn set_label(&mut self, label: String) {
+        self.label = label;
+    }
+}
+
 impl Labelled for KindSpec {
     fn label(&self) -> &str {
         &self.label
     }
+
+    fn aliases(&self) -> &[String] {
+        &self.aliases
+    }
 }
 
 impl From<InvalidKind> for ItemError {
@@ -35,12 +78,15 @@ impl TryFrom<KindDef> for KindSpec {
     type Error = ItemError;
 
     fn try_from(def: KindDef) -> Result<Self, Self::Error> {
-        let id: Kind = def.id.try_into()?;
-        let suffixes = convert_suffixes(def.suffix_range, def.suffixes)?;
+        let id: Kind = def.id.ok_or(ItemError::MissingId)?.try_into()?;
+        let suffixes = convert_suffixes(def.suffix_range, def.suffixes, def.suffix_product)?;
 
         Ok(KindSpec {
             label: def.label,
             id: id.to_owned(),
+            aliases: def.aliases,
+            names: def.names,
+            attributes: def.attributes,
             suffixes,
         })
     }
@@ -51,22 +97,68 @@ impl TryFrom<KindDef> for KindSpec {
 pub struct PosSpec {
     pub(crate) label: String,
     pub(crate) id: Pos,
+    pub(crate) aliases: Vec<String>,
+    pub(crate) names: BTreeMap<String, String>,
     pub(crate) suffixes: SuffixSpec,
     pub(crate) separate: bool,
     pub(crate) ordered: bool,
     pub(crate) hidden: bool,
 }
 
+impl PosSpec {
+    pub fn aliases(&self) -> &[String] {
+        &self.aliases
+    }
+
+    pub fn display_name(&self, locale: &str) -> &str {
+        display_name(&self.label, &self.names, locale)
+    }
+
+    pub fn suffixes(&self) -> &SuffixSpec {
+        &self.suffixes
+    }
+
+    /// Whether each player has their own region of the position.
+    pub fn is_separate(&self) -> bool {
+        self.separate
+    }
+
+    /// Whether pieces are kept in numbered slots.
+    pub fn is_ordered(&self) -> bool {
+        self.ordered
+    }
+
+    pub fn is_hidden(&self) -> bool {
+        self.hidden
+    }
+
+    /// A player can see the contents of a position unless it is hidden; the
+    /// only exception is their own region of a hidden, separate position.
+    pub fn is_visible_to(&self, region: Region, player: PlayerNum) -> bool {
+        !self.hidden || (self.separate && region == player.region())
+    }
+}
+
 impl HasId<Pos> for PosSpec {
     fn id(&self) -> Pos {
         self.id
     }
 }
 
+impl Relabel for PosSpec {
+    fn set_label(&mut self, label: String) {
+        self.label = label;
+    }
+}
+
 impl Labelled for PosSpec {
     fn label(&self) -> &str {
         &self.label
     }
+
+    fn aliases(&self) -> &[String] {
+        &self.aliases
+    }
 }
 
 impl From<InvalidPos> for ItemError {
@@ -79,12 +171,14 @@ impl TryFrom<PosDef> for PosSpec {
     type Error = ItemError;
 
     fn try_from(def: PosDef) -> Result<Self, Self::Error> {
-        let id: Pos = def.id.try_into()?;
-        let suffixes = convert_suffixes(def.suffix_range, def.suffixes)?;
+        let id: Pos = def.id.ok_or(ItemError::MissingId)?.try_into()?;
+        let suffixes = convert_suffixes(def.suffix_range, def.suffixes, None)?;
 
         Ok(PosSpec {
             label: def.label.to_owned(),
             id: id.to_owned(),
+            aliases: def.aliases,
+            names: def.names,
             suffixes,
             separate: def.separate,
             ordered: def.ordered,
@@ -98,6 +192,15 @@ impl TryFrom<PosDef> for PosSpec {
 pub struct SuffixRow {
     pub suffix: Suffix,
     pub label: String,
+    pub aliases: Vec<String>,
+    pub names: BTreeMap<String, String>,
+    pub attributes: BTreeMap<String, AttributeValue>,
+}
+
+impl SuffixRow {
+    pub fn display_name(&self, locale: &str) -> &str {
+        display_name(&self.label, &self.names, locale)
+    }
 }
 
 impl HasId<Suffix> for SuffixRow {
@@ -106,10 +209,20 @@ impl HasId<Suffix> for SuffixRow {
     }
 }
 
+impl Relabel for SuffixRow {
+    fn set_label(&mut self, label: String) {
+        self.label = label;
+    }
+}
+
 impl Labelled for SuffixRow {
     fn label(&self) -> &str {
         &self.label
     }
+
+    fn aliases(&self) -> &[String] {
+        &self.aliases
+    }
 }
 
 impl fmt::Debug for SuffixRow {
@@ -121,9 +234,15 @@ impl fmt::Debug for SuffixRow {
 impl TryFrom<SuffixDef> for SuffixRow {
     type Error = SuffixRowError;
     fn try_from(value: SuffixDef) -> Result<Self, Self::Error> {
-        let suffix = Suffix(value.id as i32);
+        let suffix = Suffix(value.id.ok_or(SuffixRowError::MissingId)? as i32);
         let label = value.label;
-        Ok(SuffixRow { suffix, label })
+        Ok(SuffixRow {
+            suffix,
+            label,
+            aliases: value.aliases,
+            names: value.names,
+            attributes: value.attributes,
+        })
     }
 }
 
@@ -144,29 +263,162 @@ impl fmt::Debug for SuffixRange {
     }
 }
 
+/// One factor of a `SuffixProduct`, such as the suits of a deck.
+#[derive(Debug)]
+pub struct Dimension {
+    pub label: String,
+    pub values: Vec<String>,
+}
+
+/// The most suffixes a `SuffixProduct` may have. Every combination is
+/// stored as a row, so this keeps a few long dimensions from exhausting
+/// memory wherever the spec is loaded.
+pub const MAX_SUFFIX_PRODUCT: usize = 4096;
+
+/// Suffixes for every combination of values of its dimensions; see
+/// `SuffixProductDef` for how they are numbered and labelled. Each suffix
+/// has an attribute per dimension, named after it, holding its value.
+pub struct SuffixProduct {
+    dimensions: Vec<Dimension>,
+    table: LookupTable<Suffix, SuffixRow>,
+}
+
+/// The values of the combination at `index`, counting from 0.
+fn values_at(dimensions: &[Dimension], mut index: usize) -> Vec<&str> {
+    let mut values = vec![""; dimensions.len()];
+    for (value, dimension) in values.iter_mut().zip(dimensions).rev() {
+        *value = &dimension.values[index % dimension.values.len()];
+        index /= dimension.values.len();
+    }
+    values
+}
+
+impl SuffixProduct {
+    pub fn dimensions(&self) -> &[Dimension] {
+        &self.dimensions
+    }
+
+    /// The value of each dimension, in order, that `suffix` stands for.
+    pub fn decompose(&self, suffix: Suffix) -> Option<Vec<&str>> {
+        if !self.table.contains_id(&suffix) {
+            return None;
+        }
+        Some(values_at(&self.dimensions, suffix.0 as usize - 1))
+    }
+
+    /// The suffix that stands for `values`, one for each dimension in order.
+    pub fn compose<T: AsRef<str>>(&self, values: &[T]) -> Option<Suffix> {
+        if values.len() != self.dimensions.len() {
+            return None;
+        }
+        let mut index = 0;
+        for (dimension, value) in self.dimensions.iter().zip(values) {
+            let position = dimension.values.iter().position(|v| v == value.as_ref())?;
+            index = index * dimension.values.len() + position;
+        }
+        Some(Suffix(index as i32 + 1))
+    }
+}
+
+impl TryFrom<SuffixProductDef> for SuffixProduct {
+    type Error = ItemError;
+
+    fn try_from(def: SuffixProductDef) -> Result<Self, Self::Error> {
+        let count = def.dimensions
+            .iter()
+            .try_fold(1usize, |count, d| count.checked_mul(d.values.len()))
+            .filter(|count| !def.dimensions.is_empty() && *count > 0 && *count <= MAX_SUFFIX_PRODUCT)
+            .ok_or(ItemError::InvalidSuffixProduct)?;
+        for (i, d) in def.dimensions.iter().enumerate() {
+            if def.dimensions[..i].iter().any(|other| other.label == d.label) {
+                return Err(ItemError::DuplicateDimension(d.label.clone()));
+            }
+        }
+        let dimensions: Vec<Dimension> = def.dimensions
+            .into_iter()
+            .map(|d| Dimension { label: d.label, values: d.values })
+            .collect();
+
+        let mut rows = Vec::with_capacity(count);
+        for index in 0..count {
+            let values = values_at(&dimensions, index);
+            rows.push(SuffixRow {
+                suffix: Suffix(index as i32 + 1),
+                label: values.join("-"),
+                aliases: Vec::new(),
+                names: BTreeMap::new(),
+                attributes: dimensions.iter()
+                    .zip(&values)
+                    .map(|(d, v)| (d.label.clone(), AttributeValue::from(*v)))
+                    .collect(),
+            });
+        }
+        let table = rows.try_into().map_err(ItemError::InvalidSuffixTable)?;
+        Ok(SuffixProduct { dimensions, table })
+    }
+}
+
 pub enum SuffixSpec {
     Empty,
     Range(SuffixRange),
     Table(LookupTable<Suffix, SuffixRow>),
+    Product(SuffixProduct),
 }
 
 impl SuffixSpec {
 
+    /// The labelled suffixes, whether listed or generated.
+    pub(crate) fn table(&self) -> Option<&LookupTable<Suffix, SuffixRow>> {
+        match self {
+            SuffixSpec::Table(table) => Some(table),
+            SuffixSpec::Product(product) => Some(&product.table),
+            _ => None,
+        }
+    }
+
     pub fn is_valid(&self, suffix: Suffix) -> bool {
         match self {
             SuffixSpec::Empty => suffix.0 == 0,
             SuffixSpec::Range(range) => range.contains_suffix(suffix),
-            SuffixSpec::Table(table) => table.contains_id(&suffix)
+            SuffixSpec::Table(table) => table.contains_id(&suffix),
+            SuffixSpec::Product(product) => product.table.contains_id(&suffix),
         }
     }
 
+    /// The label of `suffix`, if it comes from a table or product.
+    pub fn find_label(&self, suffix: Suffix) -> Option<&str> {
+        self.table()
+            .and_then(|table| table.find(&suffix))
+            .map(|r| r.label.as_str())
+    }
+
     pub fn find_by_label<T: AsRef<str>>(&self, label:T) -> Option<Suffix> {
+        self.find_by_label_with(label, LabelCase::Sensitive)
+    }
+
+    /// The name of `suffix` for `locale`, if it comes from a table or product.
+    pub fn display_name(&self, suffix: Suffix, locale: &str) -> Option<&str> {
+        self.table()
+            .and_then(|table| table.find(&suffix))
+            .map(|r| r.display_name(locale))
+    }
+
+    /// The suffix label or alias nearest to `label`, if suffixes are labelled.
+    pub fn suggest_label<T: AsRef<str>>(&self, label: T) -> Option<&str> {
+        self.table().and_then(|table| table.suggest_label(label))
+    }
+
+    pub fn find_by_label_with<T: AsRef<str>>(&self, label: T, case: LabelCase) -> Option<Suffix> {
+        self.table()
+            .and_then(|table| table.find_by_label_with(label, case))
+            .map(|r| r.suffix)
+    }
+
+    /// The dimension values `suffix` stands for, if it comes from a product.
+    pub fn decompose(&self, suffix: Suffix) -> Option<Vec<&str>> {
         match self {
-            SuffixSpec::Empty => None,
-            SuffixSpec::Range(_) => None,
-            SuffixSpec::Table(table) => {
-                table.find_by_label(label).map(|r| r.suffix)
-            }
+            SuffixSpec::Product(product) => product.decompose(suffix),
+            _ => None,
         }
     }
 }
@@ -177,6 +429,7 @@ impl fmt::Debug for SuffixSpec {
             SuffixSpec::Empty => write!(f, "empty"),
             SuffixSpec::Table(table) => write!(f, "{:?}", table),
             SuffixSpec::Range(range) => write!(f, "{:?}", range),
+            SuffixSpec::Product(product) => write!(f, "{:?}{:?}", product.dimensions, product.table),
         }
     }
 }
@@ -197,8 +450,15 @@ impl TryFrom<SuffixRangeDef> for SuffixRange {
 pub fn convert_suffixes(
     range: Option<SuffixRangeDef>,
     suffixes: Vec<SuffixDef>,
+    product: Option<SuffixProductDef>,
 ) -> Result<SuffixSpec, ItemError> {
-    if let Some(range) = range {
+    if let Some(product) = product {
+        if range.is_some() || !suffixes.is_empty() {
+            Err(ItemError::SuffixProductAndSuffixesDefined)
+        } else {
+            product.try_into().map(SuffixSpec::Product)
+        }
+    } else if let Some(range) = range {
         if !suffixes.is_empty() {
             Err(ItemError::SuffixesAndRangeDefined)
         } else {
@@ -220,8 +480,15 @@ pub fn convert_suffixes(
 }
 
 
-#[derive(Debug, PartialEq)]
-pub struct PlayerNum(u8);
+#[derive(Debug, PartialEq, Copy, Clone, Serialize, Deserialize)]
+pub struct PlayerNum(pub u8);
+
+impl PlayerNum {
+    /// The region that holds this player's share of a separate position.
+    pub fn region(self) -> Region {
+        Region(self.0 as u16)
+    }
+}
 
 #[derive(Debug)]
 pub struct GameSpec {
@@ -230,9 +497,173 @@ pub struct GameSpec {
     max_players: u8,
     pub(crate) kind_specs: LookupTable<Kind, KindSpec>,
     pub(crate) pos_specs: LookupTable<Pos, PosSpec>,
+    pub(crate) migrations: Vec<MigrationDef>,
+}
+
+
+
+impl GameSpec {
+    pub fn label(&self) -> &str {
+        &self.label
+    }
+
+    pub fn min_players(&self) -> u8 {
+        self.min_players
+    }
+
+    pub fn max_players(&self) -> u8 {
+        self.max_players
+    }
+
+    /// The kinds of piece, in id order.
+    pub fn kinds(&self) -> impl Iterator<Item=&KindSpec> {
+        self.kind_specs.iter()
+    }
+
+    /// The positions, in id order.
+    pub fn positions(&self) -> impl Iterator<Item=&PosSpec> {
+        self.pos_specs.iter()
+    }
+
+    pub fn find_kind(&self, kind: Kind) -> Option<&KindSpec> {
+        self.kind_specs.find(&kind)
+    }
+
+    /// Finds a kind by its label or one of its aliases.
+    pub fn find_kind_by_label<T: AsRef<str>>(&self, label: T) -> Option<&KindSpec> {
+        self.kind_specs.find_by_label(label)
+    }
+
+    pub fn find_kind_by_label_with<T: AsRef<str>>(&self, label: T, case: LabelCase) -> Option<&KindSpec> {
+        self.kind_specs.find_by_label_with(label, case)
+    }
+
+    pub fn find_pos(&self, pos: Pos) -> Option<&PosSpec> {
+        self.pos_specs.find(&pos)
+    }
+
+    /// Finds a position by its label or one of its aliases.
+    pub fn find_pos_by_label<T: AsRef<str>>(&self, label: T) -> Option<&PosSpec> {
+        self.pos_specs.find_by_label(label)
+    }
+
+    /// The position label or alias nearest to `label`.
+    pub fn suggest_pos_label<T: AsRef<str>>(&self, label: T) -> Option<&str> {
+        self.pos_specs.suggest_label(label)
+    }
+
+    /// The kind label or alias nearest to `label`.
+    pub fn suggest_kind_label<T: AsRef<str>>(&self, label: T) -> Option<&str> {
+        self.kind_specs.suggest_label(label)
+    }
+
+    pub fn find_pos_by_label_with<T: AsRef<str>>(&self, label: T, case: LabelCase) -> Option<&PosSpec> {
+        self.pos_specs.find_by_label_with(label, case)
+    }
+
+    /// How rows saved under the previous version of the game are upgraded.
+    pub fn migrations(&self) -> &[MigrationDef] {
+        &self.migrations
+    }
+
+    pub fn kind_label(&self, kind: Kind) -> Option<&str> {
+        self.find_kind(kind).map(|s| s.label.as_str())
+    }
+
+    pub fn pos_label(&self, pos: Pos) -> Option<&str> {
+        self.find_pos(pos).map(|s| s.label.as_str())
+    }
+
+    /// The attribute `name` of `kind`, such as the point value of a card;
+    /// see `KindSpec::attribute`.
+    pub fn attribute(&self, kind: &QKind, name: &str) -> Option<&AttributeValue> {
+        self.find_kind(kind.kind).and_then(|s| s.attribute(kind.suffix, name))
+    }
+
+    /// The name of `kind` for `locale`, falling back to its label.
+    pub fn kind_name(&self, kind: Kind, locale: &str) -> Option<&str> {
+        self.find_kind(kind).map(|s| s.display_name(locale))
+    }
+
+    /// The name of `pos` for `locale`, falling back to its label.
+    pub fn pos_name(&self, pos: Pos, locale: &str) -> Option<&str> {
+        self.find_pos(pos).map(|s| s.display_name(locale))
+    }
+
+    /// Describes `pos` by label, as `label[region]:suffix`.
+    pub fn describe_pos(&self, pos: &QPos) -> String {
+        self.describe_pos_with(pos, None)
+    }
+
+    /// Describes `pos` as `describe_pos` does, with names for `locale`.
+    pub fn describe_pos_in(&self, pos: &QPos, locale: &str) -> String {
+        self.describe_pos_with(pos, Some(locale))
+    }
+
+    fn describe_pos_with(&self, pos: &QPos, locale: Option<&str>) -> String {
+        let spec = match self.pos_specs.find(&pos.pos) {
+            Some(spec) => spec,
+            None => return format!("{:?}", pos),
+        };
+        let mut text = locale.map_or(spec.label.as_str(), |l| spec.display_name(l)).to_owned();
+        if spec.separate || pos.region.0 != 0 {
+            text.push_str(&format!("[{}]", pos.region.0));
+        }
+        if spec.ordered || pos.suffix.0 != 0 {
+            match suffix_name(&spec.suffixes, pos.suffix, locale) {
+                Some(label) if !spec.ordered => text.push_str(&format!(":{}", label)),
+                _ => text.push_str(&format!(":{}", pos.suffix.0)),
+            }
+        }
+        text
+    }
+
+    /// Describes `kind` by label, as `label:suffix`.
+    pub fn describe_kind(&self, kind: &QKind) -> String {
+        self.describe_kind_with(kind, None)
+    }
+
+    /// Describes `kind` as `describe_kind` does, with names for `locale`.
+    pub fn describe_kind_in(&self, kind: &QKind, locale: &str) -> String {
+        self.describe_kind_with(kind, Some(locale))
+    }
+
+    fn describe_kind_with(&self, kind: &QKind, locale: Option<&str>) -> String {
+        let spec = match self.kind_specs.find(&kind.kind) {
+            Some(spec) => spec,
+            None => return format!("{:?}", kind),
+        };
+        let name = locale.map_or(spec.label.as_str(), |l| spec.display_name(l));
+        match (&spec.suffixes, suffix_name(&spec.suffixes, kind.suffix, locale)) {
+            (SuffixSpec::Empty, _) => name.to_owned(),
+            (_, Some(label)) => format!("{}:{}", name, label),
+            (_, None) => format!("{}:{}", name, kind.suffix.0),
+        }
+    }
+
+    /// Whether `player` can see what is at `pos`; unknown positions are never visible.
+    pub(crate) fn is_visible(&self, pos: &QPos, player: PlayerNum) -> bool {
+        self.pos_specs
+            .find(&pos.pos)
+            .is_some_and(|s| s.is_visible_to(pos.region, player))
+    }
 }
 
+/// The label of `suffix`, or its name for `locale` if there is one.
+fn suffix_name<'a>(suffixes: &'a SuffixSpec, suffix: Suffix, locale: Option<&str>) -> Option<&'a str> {
+    match locale {
+        Some(locale) => suffixes.display_name(suffix, locale),
+        None => suffixes.find_label(suffix),
+    }
+}
 
+fn check_suffix_map(map: &SuffixMapDef) -> Result<(), Error> {
+    let span = map.from.max as i64 - map.from.min as i64;
+    if span < 0 || map.to as i64 + span > i32::MAX as i64 {
+        return Err(Error::InvalidSuffixMap(map.clone()));
+    }
+    Ok(())
+}
 
 fn convert_player_num(input: u32) -> Result<u8, Error> {
     input
@@ -262,26 +693,112 @@ impl TryFrom<GameDef> for GameSpec {
         let pos_specs: LookupTable<Pos, PosSpec> =
             pos_specs.try_into().map_err(Error::InvalidPosTable)?;
 
+        for migration in &value.migrations {
+            if let MigrationDef::MapPosSuffixes(map) | MigrationDef::MapKindSuffixes(map) = migration {
+                check_suffix_map(map)?;
+            }
+        }
+
         Ok(GameSpec {
             label: value.label.to_owned(),
             min_players: convert_player_num(value.min_players)?,
             max_players: convert_player_num(value.max_players)?,
             kind_specs,
             pos_specs,
+            migrations: value.migrations,
         })
     }
 }
 
+/// The inverse of `convert_suffixes`.
+fn suffix_defs(spec: &SuffixSpec) -> (Option<SuffixRangeDef>, Vec<SuffixDef>, Option<SuffixProductDef>) {
+    match spec {
+        SuffixSpec::Empty => (None, Vec::new(), None),
+        SuffixSpec::Range(range) => {
+            (Some(SuffixRangeDef { min: range.min.0, max: range.max.0 }), Vec::new(), None)
+        }
+        SuffixSpec::Product(product) => {
+            let dimensions = product.dimensions
+                .iter()
+                .map(|d| DimensionDef { label: d.label.clone(), values: d.values.clone() })
+                .collect();
+            (None, Vec::new(), Some(SuffixProductDef { dimensions }))
+        }
+        SuffixSpec::Table(table) => {
+            let suffixes = table.iter()
+                .map(|r| SuffixDef {
+                    label: r.label.clone(),
+                    id: Some(r.suffix.0 as u32),
+                    aliases: r.aliases.clone(),
+                    names: r.names.clone(),
+                    attributes: r.attributes.clone(),
+                })
+                .collect();
+            (None, suffixes, None)
+        }
+    }
+}
+
+impl From<&KindSpec> for KindDef {
+    fn from(spec: &KindSpec) -> Self {
+        let (suffix_range, suffixes, suffix_product) = suffix_defs(&spec.suffixes);
+        KindDef {
+            label: spec.label.clone(),
+            id: Some(spec.id.into()),
+            aliases: spec.aliases.clone(),
+            names: spec.names.clone(),
+            attributes: spec.attributes.clone(),
+            suffix_range,
+            suffixes,
+            suffix_product,
+        }
+    }
+}
+
+impl From<&PosSpec> for PosDef {
+    fn from(spec: &PosSpec) -> Self {
+        // Only kinds have products of suffixes.
+        let (suffix_range, suffixes, _) = suffix_defs(&spec.suffixes);
+        PosDef {
+            label: spec.label.clone(),
+            id: Some(spec.id.into()),
+            aliases: spec.aliases.clone(),
+            names: spec.names.clone(),
+            suffix_range,
+            suffixes,
+            separate: spec.separate,
+            ordered: spec.ordered,
+            hidden: spec.hidden,
+        }
+    }
+}
+
+/// The canonical definition of a spec: every id filled in and every list
+/// sorted by id, so equal specs give equal definitions.
+impl From<&GameSpec> for GameDef {
+    fn from(spec: &GameSpec) -> Self {
+        GameDef {
+            label: spec.label.clone(),
+            min_players: spec.min_players as u32,
+            max_players: spec.max_players as u32,
+            kind_defs: spec.kinds().map(KindDef::from).collect(),
+            pos_defs: spec.positions().map(PosDef::from).collect(),
+            migrations: spec.migrations.clone(),
+        }
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
     use crate::coords::Suffix;
-    use crate::defs::{GameDefBuilder, KindDef, PosDef, SuffixDef};
+    use crate::defs::{GameDefBuilder, KindDef, KindDefBuilder, PosDef, SuffixDef};
     use std::convert::TryInto;
     use crate::lookup::Collision;
     use crate::error::ItemError::InvalidSuffixTable;
 
     #[test]
+    #[allow(clippy::bool_assert_comparison)]
     fn can_convert_def_into_spec() {
         let def = GameDefBuilder::bld("whist")
             .min_players(3)
@@ -431,4 +948,245 @@ mod test {
         ), err);
     }
 
+    #[test]
+    fn can_introspect_spec() {
+        let spec: GameSpec = GameDefBuilder::bld("whist")
+            .min_players(3)
+            .max_players(5)
+            .kind(KindDef::bld("leader").id(3))
+            .kind(KindDef::bld("card").suffix_range(1, 52).id(1))
+            .pos(PosDef::bld("trick").separate().id(2))
+            .pos(PosDef::bld("deck").hidden().ordered().id(1))
+            .build()
+            .try_into()
+            .unwrap();
+
+        assert_eq!("whist", spec.label());
+        assert_eq!((3, 5), (spec.min_players(), spec.max_players()));
+
+        let kinds: Vec<&str> = spec.kinds().map(|k| k.label.as_str()).collect();
+        assert_eq!(vec!["card", "leader"], kinds);
+        let positions: Vec<&str> = spec.positions().map(|p| p.label()).collect();
+        assert_eq!(vec!["deck", "trick"], positions);
+
+        let deck = spec.find_pos_by_label("deck").unwrap();
+        assert!(deck.is_hidden() && deck.is_ordered() && !deck.is_separate());
+        assert!(matches!(deck.suffixes(), SuffixSpec::Empty));
+
+        assert_eq!(Some("trick"), spec.pos_label(2.try_into().unwrap()));
+        assert_eq!(Some("leader"), spec.kind_label(3.try_into().unwrap()));
+        assert_eq!(None, spec.kind_label(2.try_into().unwrap()));
+    }
+
+    #[test]
+    fn can_convert_spec_back_into_canonical_def() {
+        let def = GameDefBuilder::bld("whist")
+            .min_players(3)
+            .max_players(5)
+            .kind(
+                KindDef::bld("suit")
+                    .suffix(SuffixDef::bld("clubs").id(2))
+                    .suffix(SuffixDef::bld("hearts").alias("♥").id(1)),
+            )
+            .kind(KindDef::bld("card").suffix_range(1, 52))
+            .pos(PosDef::bld("hand").alias("hands").hidden().separate().id(3))
+            .pos(PosDef::bld("deck").ordered().id(1))
+            .build();
+        let spec: GameSpec = def.clone().try_into().unwrap();
+
+        let canonical = GameDef::from(&spec);
+        let expected = GameDefBuilder::bld("whist")
+            .min_players(3)
+            .max_players(5)
+            .kind(
+                KindDef::bld("suit")
+                    .suffix(SuffixDef::bld("hearts").alias("♥"))
+                    .suffix(SuffixDef::bld("clubs")),
+            )
+            .kind(KindDef::bld("card").suffix_range(1, 52))
+            .pos(PosDef::bld("deck").ordered())
+            .pos(PosDef::bld("hand").alias("hands").hidden().separate().id(3))
+            .build();
+        assert_eq!(expected, canonical);
+
+        let again: GameSpec = canonical.clone().try_into().unwrap();
+        assert_eq!(canonical, GameDef::from(&again));
+    }
+
+    #[test]
+    fn can_find_items_by_alias() {
+        let yaml = "
+label: whist
+min_players: 3
+max_players: 5
+kind_defs:
+  - label: suit
+    id: 1
+    aliases: [suits]
+    suffixes:
+      - {label: hearts, id: 1, aliases: [Hearts, ♥]}
+pos_defs:
+  - {label: hand, id: 1, aliases: [Hand]}
+";
+        let def: GameDef = serde_yaml::from_str(yaml).unwrap();
+        let spec: GameSpec = def.clone().try_into().unwrap();
+
+        let suit = spec.find_kind_by_label("suits").unwrap();
+        assert_eq!(Some(Suffix(1)), suit.suffixes.find_by_label("♥"));
+        assert_eq!(Some(Suffix(1)), suit.suffixes.find_by_label_with("HEARTS", LabelCase::Insensitive));
+        assert!(spec.find_pos_by_label("hAND").is_none());
+        assert!(spec.find_pos_by_label_with("hAND", LabelCase::Insensitive).is_some());
+        assert_eq!(def, serde_yaml::from_str(&serde_yaml::to_string(&def).unwrap()).unwrap());
+    }
+
+    #[test]
+    fn can_not_convert_def_if_an_alias_collides() {
+        let err: Error = GameDefBuilder::bld("whist")
+            .kind(KindDef::bld("card"))
+            .kind(KindDef::bld("suit").alias("card"))
+            .build()
+            .try_into()
+            .map(|_: GameSpec| ())
+            .unwrap_err();
+
+        assert_eq!(Error::InvalidKindTable(Collision::LabelCollision("card".to_owned())), err);
+    }
+
+    #[test]
+    fn display_names_fall_back_to_language_then_label() {
+        let spec: GameSpec = GameDefBuilder::bld("whist")
+            .kind(
+                KindDef::bld("suit")
+                    .name("fr", "couleur")
+                    .suffix(SuffixDef::bld("hearts").name("fr", "Cœurs").name("pt-BR", "Copas")),
+            )
+            .pos(PosDef::bld("hand").name("fr", "main"))
+            .build()
+            .try_into()
+            .unwrap();
+        let suit = spec.find_kind_by_label("suit").unwrap();
+        let hand = spec.find_pos_by_label("hand").unwrap();
+
+        assert_eq!(Some("couleur"), spec.kind_name(suit.id, "fr"));
+        assert_eq!(Some("couleur"), spec.kind_name(suit.id, "fr_BE"));
+        assert_eq!(Some("suit"), spec.kind_name(suit.id, "en"));
+        assert_eq!(Some("main"), spec.pos_name(hand.id, "fr-CA"));
+        assert_eq!(Some("Copas"), suit.suffixes.display_name(Suffix(1), "pt-BR"));
+        assert_eq!(Some("hearts"), suit.suffixes.display_name(Suffix(1), "pt"));
+
+        let hearts = QKind { kind: suit.id, suffix: Suffix(1) };
+        assert_eq!("suit:hearts", spec.describe_kind(&hearts));
+        assert_eq!("couleur:Cœurs", spec.describe_kind_in(&hearts, "fr"));
+        let def = GameDef::from(&spec);
+        assert_eq!(Some("Cœurs"), def.kind_defs[0].suffixes[0].names.get("fr").map(String::as_str));
+    }
+
+    #[test]
+    fn can_query_attributes_by_qkind() {
+        let yaml = "
+label: whist
+min_players: 4
+max_players: 4
+kind_defs:
+  - label: card
+    id: 1
+    attributes: {points: 0}
+    suffix_range: {min: 1, max: 52}
+  - label: suit
+    id: 2
+    attributes: {points: 0, trump: false}
+    suffixes:
+      - {label: hearts, id: 1, attributes: {colour: red, points: 1}}
+      - {label: spades, id: 2, attributes: {colour: black, trump: true, code: '13'}}
+pos_defs:
+  - {label: hand, id: 1}
+";
+        let def: GameDef = serde_yaml::from_str(yaml).unwrap();
+        let spec: GameSpec = def.clone().try_into().unwrap();
+        let kind = |kind: u32, suffix: i32| QKind { kind: kind.try_into().unwrap(), suffix: Suffix(suffix) };
+
+        assert_eq!(Some(0), spec.attribute(&kind(1, 12), "points").and_then(AttributeValue::as_int));
+        assert_eq!(Some(1), spec.attribute(&kind(2, 1), "points").and_then(AttributeValue::as_int));
+        assert_eq!(Some(0), spec.attribute(&kind(2, 2), "points").and_then(AttributeValue::as_int));
+        assert_eq!(Some("red"), spec.attribute(&kind(2, 1), "colour").and_then(AttributeValue::as_str));
+        assert_eq!(Some(true), spec.attribute(&kind(2, 2), "trump").and_then(AttributeValue::as_bool));
+        assert_eq!(Some(false), spec.attribute(&kind(2, 1), "trump").and_then(AttributeValue::as_bool));
+        assert_eq!(Some(&AttributeValue::Str("13".to_owned())), spec.attribute(&kind(2, 2), "code"));
+        assert_eq!(None, spec.attribute(&kind(1, 12), "colour"));
+
+        let again: GameDef = serde_yaml::from_str(&serde_yaml::to_string(&def).unwrap()).unwrap();
+        assert_eq!(def, again);
+        assert_eq!(def.kind_defs[1].suffixes[1].attributes, GameDef::from(&spec).kind_defs[1].suffixes[1].attributes);
+    }
+
+    #[test]
+    fn can_generate_suffixes_from_a_product() {
+        let yaml = "
+label: whist
+min_players: 4
+max_players: 4
+kind_defs:
+  - label: card
+    id: 1
+    suffix_product:
+      dimensions:
+        - {label: suit, values: [hearts, clubs, diamonds, spades]}
+        - {label: rank, values: [ace, '2', '3', '4', '5', '6', '7', '8', '9', '10', jack, queen, king]}
+pos_defs:
+  - {label: deck, id: 1}
+";
+        let def: GameDef = serde_yaml::from_str(yaml).unwrap();
+        let spec: GameSpec = def.clone().try_into().unwrap();
+        let card = spec.find_kind_by_label("card").unwrap();
+
+        assert!(card.suffixes.is_valid(Suffix(1)));
+        assert!(card.suffixes.is_valid(Suffix(52)));
+        assert!(!card.suffixes.is_valid(Suffix(53)));
+        assert_eq!(Some("hearts-ace"), card.suffixes.find_label(Suffix(1)));
+        assert_eq!(Some("clubs-ace"), card.suffixes.find_label(Suffix(14)));
+        assert_eq!(Some(Suffix(12)), card.suffixes.find_by_label("hearts-queen"));
+        assert_eq!(Some(vec!["spades", "king"]), card.suffixes.decompose(Suffix(52)));
+        assert_eq!(None, card.suffixes.decompose(Suffix(0)));
+        assert_eq!(
+            Some("diamonds"),
+            spec.attribute(&QKind { kind: card.id, suffix: Suffix(30) }, "suit").and_then(AttributeValue::as_str)
+        );
+
+        match &card.suffixes {
+            SuffixSpec::Product(product) => {
+                assert_eq!(Some(Suffix(38)), product.compose(&["diamonds", "queen"]));
+                assert_eq!(None, product.compose(&["diamonds", "emperor"]));
+                assert_eq!(None, product.compose(&["diamonds"]));
+                assert_eq!(vec!["suit", "rank"], product.dimensions().iter().map(|d| d.label.as_str()).collect::<Vec<_>>());
+            }
+            other => panic!("expected a product, got {:?}", other),
+        }
+
+        assert_eq!(def, serde_yaml::from_str(&serde_yaml::to_string(&def).unwrap()).unwrap());
+        assert_eq!(def.kind_defs, GameDef::from(&spec).kind_defs);
+    }
+
+    #[test]
+    fn can_not_convert_invalid_products() {
+        let convert = |kind: KindDefBuilder| -> Result<GameSpec, Error> {
+            GameDefBuilder::bld("whist").kind(kind).build().try_into()
+        };
+
+        let err = convert(KindDef::bld("card").dimension("suit", &["hearts"]).suffix_range(1, 4)).unwrap_err();
+        assert_eq!(Error::InvalidKind(ItemError::SuffixProductAndSuffixesDefined), err);
+
+        let err = convert(KindDef::bld("card").dimension("suit", &["hearts"]).dimension("rank", &[])).unwrap_err();
+        assert_eq!(Error::InvalidKind(ItemError::InvalidSuffixProduct), err);
+
+        let values: Vec<String> = (0..1000).map(|v| v.to_string()).collect();
+        let values: Vec<&str> = values.iter().map(String::as_str).collect();
+        let err = convert(KindDef::bld("card").dimension("a", &values).dimension("b", &values).dimension("c", &values)).unwrap_err();
+        assert_eq!(Error::InvalidKind(ItemError::InvalidSuffixProduct), err);
+
+        let err = convert(KindDef::bld("card").dimension("suit", &["hearts"]).dimension("suit", &["clubs"])).unwrap_err();
+        assert_eq!(Error::InvalidKind(ItemError::DuplicateDimension("suit".to_owned())), err);
+
+        let err = convert(KindDef::bld("card").dimension("suit", &["hearts", "hearts"])).unwrap_err();
+        assert_eq!(Error::InvalidKind(InvalidSuffixTable(Collision::LabelCollision("hearts".to_owned()))), err);
+    }
 }
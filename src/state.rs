@@ -1,21 +1,31 @@
-use crate::specs::GameSpec;
+use crate::specs::{GameSpec, PlayerNum};
+#[cfg(feature = "persistent")]
+use im::{HashMap, OrdMap as BTreeMap, Vector as Shards};
+#[cfg(not(feature = "persistent"))]
 use std::collections::{HashMap, BTreeMap};
+#[cfg(not(feature = "persistent"))]
+use std::vec::Vec as Shards;
+use std::convert::TryFrom;
+use std::sync::Arc;
+use crate::lookup::IdStore;
 use crate::coords::{Pos, QKind, Suffix, QPos, Region};
+use crate::diff::{Diff, Subscriber};
 use crate::state::Shard::{Ordered, Unordered};
 
+use serde::{Deserialize, Serialize};
 use std::fmt;
-use std::collections::hash_map::Entry;
 
 trait ShardLike {
     fn len(&self) -> usize;
     fn export_rows(&self, target: &mut Vec<ExportRow>);
+    fn content_hash(&self) -> u64;
 }
 
-#[derive(Ord, PartialOrd, Eq, PartialEq)]
+#[derive(Ord, PartialOrd, Eq, PartialEq, Copy, Clone, Serialize, Deserialize)]
 pub struct ExportRow {
-    pos: QPos,
-    kind: QKind,
-    count: u32,
+    pub pos: QPos,
+    pub kind: QKind,
+    pub count: u32,
 }
 
 impl fmt::Debug for ExportRow {
@@ -24,16 +34,45 @@ impl fmt::Debug for ExportRow {
     }
 }
 
-#[derive(Eq, PartialEq, Hash)]
+fn mix(mut z: u64) -> u64 {
+    // splitmix64 finaliser
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+impl ExportRow {
+    /// The Zobrist key of this row; a state hashes to the xor of its rows' keys.
+    pub fn zobrist_key(&self) -> u64 {
+        let pos = (self.pos.pos.as_u32() as u64) << 48
+            | (self.pos.region.0 as u64) << 32
+            | self.pos.suffix.0 as u32 as u64;
+        let kind = (self.kind.kind.as_u32() as u64) << 32
+            | self.kind.suffix.0 as u32 as u64;
+        mix(mix(mix(pos) ^ kind) ^ self.count as u64)
+    }
+}
+
+#[derive(Eq, PartialEq, Hash, Copy, Clone)]
 struct Key {
     pos_suffix: Suffix,
     kind: QKind,
 }
 
+/// What each row held before a transaction first changed it, so that the
+/// transaction's diff costs as much as the rows it changed.
+#[derive(Default)]
+struct Journal {
+    counts: BTreeMap<(QPos, QKind), u32>,
+    slots: BTreeMap<QPos, Option<QKind>>,
+}
+
+#[derive(Clone)]
 struct UnorderedShard {
     pos: Pos,
     region: Region,
     counts: HashMap<Key, u32>,
+    hash: u64,
 }
 
 impl UnorderedShard {
@@ -42,8 +81,60 @@ impl UnorderedShard {
             pos,
             region,
             counts: HashMap::new(),
+            hash: 0,
+        }
+    }
+
+    fn row(&self, key: &Key, count: u32) -> ExportRow {
+        let pos = QPos { pos: self.pos, region: self.region, suffix: key.pos_suffix };
+        ExportRow { pos, kind: key.kind, count }
+    }
+
+    /// Sets the count of `key`, keeping the hash and journal in step.
+    fn set_count(&mut self, key: Key, count: u32, journal: &mut Journal) {
+        let old = self.counts.get(&key).copied();
+        let row = self.row(&key, 0);
+        journal.counts.entry((row.pos, row.kind)).or_insert_with(|| old.unwrap_or(0));
+        if let Some(old) = old {
+            self.hash ^= self.row(&key, old).zobrist_key();
+        }
+        if count == 0 {
+            self.counts.remove(&key);
+        } else {
+            self.hash ^= self.row(&key, count).zobrist_key();
+            self.counts.insert(key, count);
+        }
+    }
+
+    /// Adds `count` pieces of `key`, unless the total would not fit.
+    fn add(&mut self, key: Key, count: u32, journal: &mut Journal) -> bool {
+        match self.counts.get(&key).copied().unwrap_or(0).checked_add(count) {
+            Some(total) => {
+                self.set_count(key, total, journal);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Removes `count` pieces of `key`, if there are that many.
+    fn take(&mut self, key: Key, count: u32, journal: &mut Journal) -> bool {
+        match self.counts.get(&key).copied() {
+            Some(total) if total >= count => {
+                self.set_count(key, total - count, journal);
+                true
+            }
+            _ => false,
         }
     }
+
+    fn set_row(&mut self, row: &ExportRow, journal: &mut Journal) {
+        self.set_count(Key { pos_suffix: row.pos.suffix, kind: row.kind }, row.count, journal);
+    }
+
+    fn remove_row(&mut self, row: &ExportRow, journal: &mut Journal) {
+        self.set_count(Key { pos_suffix: row.pos.suffix, kind: row.kind }, 0, journal);
+    }
 }
 
 impl ShardLike for UnorderedShard {
@@ -53,20 +144,21 @@ impl ShardLike for UnorderedShard {
 
     fn export_rows(&self, target: &mut Vec<ExportRow>) {
         for (key, count) in &self.counts {
-            let pos = QPos { pos: self.pos, region: self.region, suffix: key.pos_suffix };
-            target.push(ExportRow {
-                pos,
-                kind: key.kind,
-                count: *count,
-            });
+            target.push(self.row(key, *count));
         }
     }
+
+    fn content_hash(&self) -> u64 {
+        self.hash
+    }
 }
 
+#[derive(Clone)]
 struct OrderedShard {
     pos: Pos,
     region: Region,
     counts: BTreeMap<Suffix, QKind>,
+    hash: u64,
 }
 
 impl OrderedShard {
@@ -75,6 +167,92 @@ impl OrderedShard {
             pos,
             region,
             counts: BTreeMap::new(),
+            hash: 0,
+        }
+    }
+
+    fn row(&self, slot: Suffix, kind: QKind) -> ExportRow {
+        let pos = QPos { pos: self.pos, region: self.region, suffix: slot };
+        ExportRow { pos, kind, count: 1 }
+    }
+
+    /// Puts `kind` into `slot` (or empties it), keeping the hash and journal in step.
+    fn set_slot(&mut self, slot: Suffix, kind: Option<QKind>, journal: &mut Journal) {
+        let old = self.counts.get(&slot).copied();
+        if old == kind {
+            return;
+        }
+        let pos = QPos { pos: self.pos, region: self.region, suffix: slot };
+        journal.slots.entry(pos).or_insert(old);
+        if let Some(old) = old {
+            self.hash ^= self.row(slot, old).zobrist_key();
+        }
+        match kind {
+            Some(kind) => {
+                self.hash ^= self.row(slot, kind).zobrist_key();
+                self.counts.insert(slot, kind);
+            }
+            None => {
+                self.counts.remove(&slot);
+            }
+        }
+    }
+
+    /// Inserts `count` pieces starting at slot `at`, moving later pieces up.
+    /// Only the slots from `at` on are touched, so appending is cheap.
+    fn insert(&mut self, at: Suffix, kind: QKind, count: u32, journal: &mut Journal) -> bool {
+        if at.0 < 0 || at.0 as usize > self.counts.len() {
+            return false;
+        }
+        let count = match i32::try_from(count) {
+            Ok(count) if (self.counts.len() as i32).checked_add(count).is_some() => count,
+            _ => return false,
+        };
+        let later: Vec<(Suffix, QKind)> = self.counts
+            .range(at..)
+            .map(|(slot, kind)| (*slot, *kind))
+            .collect();
+        for (slot, kind) in later.into_iter().rev() {
+            self.set_slot(Suffix(slot.0 + count), Some(kind), journal);
+        }
+        for offset in 0..count {
+            self.set_slot(Suffix(at.0 + offset), Some(kind), journal);
+        }
+        true
+    }
+
+    /// Removes `count` pieces of `kind` starting at slot `at`, moving later pieces down.
+    fn take(&mut self, at: Suffix, kind: QKind, count: u32, journal: &mut Journal) -> bool {
+        let count = match i32::try_from(count) {
+            Ok(count) if at.0.checked_add(count).is_some() => count,
+            _ => return false,
+        };
+        let matches = (0..count)
+            .all(|offset| self.counts.get(&Suffix(at.0 + offset)) == Some(&kind));
+        if !matches {
+            return false;
+        }
+        let later: Vec<(Suffix, QKind)> = self.counts
+            .range(Suffix(at.0 + count)..)
+            .map(|(slot, kind)| (*slot, *kind))
+            .collect();
+        let end = later.last().map_or(at.0 + count, |(slot, _)| slot.0 + 1);
+        for (slot, kind) in later {
+            self.set_slot(Suffix(slot.0 - count), Some(kind), journal);
+        }
+        for slot in end - count..end {
+            self.set_slot(Suffix(slot), None, journal);
+        }
+        true
+    }
+
+    fn set_row(&mut self, row: &ExportRow, journal: &mut Journal) {
+        self.set_slot(row.pos.suffix, Some(row.kind), journal);
+    }
+
+    fn remove_row(&mut self, row: &ExportRow, journal: &mut Journal) {
+        if self.counts.get(&row.pos.suffix) == Some(&row.kind) {
+            self.set_slot(row.pos.suffix, None, journal);
         }
     }
 }
@@ -85,17 +263,14 @@ impl ShardLike for OrderedShard {
     }
 
     fn export_rows(&self, target: &mut Vec<ExportRow>) {
-        for (pos_suffix, kind) in &self.counts {
-            let pos = QPos { pos: self.pos, region: self.region, suffix: *pos_suffix };
-            target.push(
-                ExportRow {
-                    pos,
-                    kind: *kind,
-                    count: 1,
-                }
-            );
+        for (slot, kind) in &self.counts {
+            target.push(self.row(*slot, *kind));
         }
     }
+
+    fn content_hash(&self) -> u64 {
+        self.hash
+    }
 }
 
 enum MutShard<'a> {
@@ -103,6 +278,7 @@ enum MutShard<'a> {
     Unordered(&'a mut UnorderedShard),
 }
 
+#[derive(Clone)]
 struct Regional<T> where T: ShardLike {
     regions: HashMap<Region, T>
 }
@@ -125,8 +301,13 @@ impl<T> ShardLike for Regional<T> where T: ShardLike {
             region.export_rows(target)
         }
     }
+
+    fn content_hash(&self) -> u64 {
+        self.regions.values().fold(0, |hash, s| hash ^ s.content_hash())
+    }
 }
 
+#[derive(Clone)]
 enum Shard {
     Ordered(Regional<OrderedShard>),
     Unordered(Regional<UnorderedShard>),
@@ -146,10 +327,48 @@ impl ShardLike for Shard {
             Unordered(s) => s.export_rows(target),
         }
     }
+
+    fn content_hash(&self) -> u64 {
+        match self {
+            Ordered(s) => s.content_hash(),
+            Unordered(s) => s.content_hash(),
+        }
+    }
 }
 
 impl Shard {
-    fn find_or_create_shard_mut(&mut self, pos: Pos, region: Region) -> MutShard {
+    fn extend_diff(&self, diff: &mut Diff, before: Vec<ExportRow>, after: Vec<ExportRow>) {
+        match self {
+            Ordered(_) => diff.extend_between_slots(before, after),
+            Unordered(_) => diff.extend_between(before, after),
+        }
+    }
+
+    /// The row at `pos` for `kind` as it stands, if there is one.
+    fn find_row(&self, pos: QPos, kind: QKind) -> Option<ExportRow> {
+        let count = match self {
+            Ordered(s) => s.regions.get(&pos.region)
+                .and_then(|shard| shard.counts.get(&pos.suffix))
+                .filter(|k| **k == kind)
+                .map(|_| 1),
+            Unordered(s) => s.regions.get(&pos.region)
+                .and_then(|shard| shard.counts.get(&Key { pos_suffix: pos.suffix, kind }))
+                .copied(),
+        };
+        count.map(|count| ExportRow { pos, kind, count })
+    }
+
+    /// The piece in slot `pos` of an ordered position, if there is one.
+    fn find_slot(&self, pos: QPos) -> Option<QKind> {
+        match self {
+            Ordered(s) => s.regions.get(&pos.region)
+                .and_then(|shard| shard.counts.get(&pos.suffix))
+                .copied(),
+            Unordered(_) => None,
+        }
+    }
+
+    fn find_or_create_shard_mut(&mut self, pos: Pos, region: Region) -> MutShard<'_> {
         match self {
             Ordered(shard) => {
                 MutShard::Ordered(
@@ -168,7 +387,7 @@ impl Shard {
         }
     }
 
-    fn find_shard_mut(&mut self, region: Region) -> Option<MutShard> {
+    fn find_shard_mut(&mut self, region: Region) -> Option<MutShard<'_>> {
         match self {
             Ordered(shard) => shard.regions
                 .get_mut(&region)
@@ -181,114 +400,310 @@ impl Shard {
     }
 }
 
+/// The pieces in play, grouped into a shard per position.
+///
+/// Shards are kept in a vector, in position order, found through an index
+/// by position id that is shared between clones. With the `persistent`
+/// feature the vector is structurally shared, so cloning a state and
+/// applying a command to the clone is cheap.
 pub struct State {
-    shards: HashMap<Pos, Shard>
+    index: Arc<IdStore<Pos, usize>>,
+    shards: Shards<Shard>,
+    subscribers: Vec<Box<dyn Subscriber + Send>>,
+}
+
+/// Clones the pieces but not the subscribers, which stay with the original.
+impl Clone for State {
+    fn clone(&self) -> Self {
+        State {
+            index: self.index.clone(),
+            shards: self.shards.clone(),
+            subscribers: Vec::new(),
+        }
+    }
 }
 
 impl State {
     pub fn new(spec: &GameSpec) -> State {
-        let mut shards = HashMap::new();
+        let mut index = IdStore::new();
+        let mut shards = Vec::new();
         for pos_spec in &spec.pos_specs {
             let shard = if pos_spec.ordered {
                 Ordered(Regional::new())
             } else {
                 Unordered(Regional::new())
             };
-            shards.insert(pos_spec.id, shard);
+            index.insert(pos_spec.id, shards.len()).ok();
+            shards.push(shard);
         }
-        State { shards }
+        State {
+            index: Arc::new(index),
+            shards: shards.into_iter().collect(),
+            subscribers: Vec::new(),
+        }
+    }
+
+    fn shard(&self, pos: &Pos) -> Option<&Shard> {
+        self.index.get(pos).map(|slot| &self.shards[*slot])
+    }
+
+    fn shard_mut(&mut self, pos: &Pos) -> Option<&mut Shard> {
+        let slot = *self.index.get(pos)?;
+        self.shards.get_mut(slot)
     }
 
     fn len(&self) -> usize {
-        self.shards.values().map(ShardLike::len).sum()
+        self.shards.iter().map(ShardLike::len).sum()
+    }
+
+    /// A hash of the state's contents that does not depend on the order
+    /// pieces were created in. It is the xor of every row's `zobrist_key`
+    /// and is kept up to date as transactions apply.
+    pub fn content_hash(&self) -> u64 {
+        self.shards.iter().fold(0, |hash, s| hash ^ s.content_hash())
+    }
+
+    /// The changes that turn this state into `other`, compared shard by shard.
+    /// Unordered positions report count changes; ordered positions report the
+    /// slots that were filled or emptied, and those now holding a different
+    /// piece as reordered.
+    pub fn diff(&self, other: &State) -> Diff {
+        let mut diff = Diff::new();
+        for (pos, slot) in self.index.iter() {
+            let shard = &self.shards[*slot];
+            let mut before = Vec::new();
+            shard.export_rows(&mut before);
+            let mut after = Vec::new();
+            if let Some(other) = other.shard(&pos) {
+                other.export_rows(&mut after);
+            }
+            shard.extend_diff(&mut diff, before, after);
+        }
+        for (pos, slot) in other.index.iter() {
+            let shard = &other.shards[*slot];
+            if self.shard(&pos).is_none() {
+                let mut after = Vec::new();
+                shard.export_rows(&mut after);
+                shard.extend_diff(&mut diff, Vec::new(), after);
+            }
+        }
+        diff
+    }
+
+    /// Applies a diff produced by `diff` or `commit`, notifying subscribers.
+    pub fn apply_diff(&mut self, diff: &Diff) -> Result<(), CmdError> {
+        let mut tx = self.start_tx();
+        tx.apply_diff(diff)?;
+        tx.commit();
+        Ok(())
     }
 
     pub fn export_rows(&self) -> Vec<ExportRow> {
         let mut rows = Vec::with_capacity(self.len());
-        for shard in self.shards.values() {
+        for shard in self.shards.iter() {
             shard.export_rows(&mut rows);
         }
         rows
     }
 
-    pub fn start_tx(&mut self) -> Transaction {
+    /// The rows `player` is allowed to see.
+    pub fn export_player_rows(&self, spec: &GameSpec, player: PlayerNum) -> Vec<ExportRow> {
+        let mut rows = self.export_rows();
+        rows.retain(|row| spec.is_visible(&row.pos, player));
+        rows
+    }
+
+    /// Registers a subscriber that receives the diff of every committed transaction.
+    pub fn subscribe<S: Subscriber + Send + 'static>(&mut self, subscriber: S) {
+        self.subscribers.push(Box::new(subscriber));
+    }
+
+    pub fn start_tx(&mut self) -> Transaction<'_> {
         Transaction {
-            state: self
+            state: self,
+            journal: Journal::default(),
         }
     }
 }
 
 pub struct Transaction<'a> {
     state: &'a mut State,
+    journal: Journal,
 }
 
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
 pub struct CreatePieces {
-    pos: QPos,
-    kind: QKind,
-    count: u32,
+    pub pos: QPos,
+    pub kind: QKind,
+    pub count: u32,
+}
+
+/// Moves pieces of one kind; from an ordered position they are taken
+/// starting at the `from` slot.
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct MovePieces {
+    pub from: QPos,
+    pub to: QPos,
+    pub kind: QKind,
+    pub count: u32,
 }
 
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
 pub enum Cmd {
-    CreatePieces(CreatePieces)
+    CreatePieces(CreatePieces),
+    MovePieces(MovePieces),
 }
 
+#[derive(Debug, PartialEq)]
 pub enum CmdError {
-    NoSuchPos(Pos)
+    NoSuchPos(Pos),
+    InvalidSlot(QPos),
+    NotEnoughPieces(QPos, QKind),
+    /// There would be more than `u32::MAX` pieces of the kind at the position.
+    TooManyPieces(QPos, QKind),
 }
 
 impl<'a> Transaction<'a> {
     pub fn apply(&mut self, cmd: &Cmd) -> Result<(), CmdError> {
         match cmd {
             Cmd::CreatePieces(cmd) => self.create_pieces(cmd),
+            Cmd::MovePieces(cmd) => self.move_pieces(cmd),
+        }
+    }
+
+    fn move_pieces(&mut self, cmd: &MovePieces) -> Result<(), CmdError> {
+        self.remove_pieces(cmd.from, cmd.kind, cmd.count)?;
+        let create = CreatePieces { pos: cmd.to, kind: cmd.kind, count: cmd.count };
+        if let Err(e) = self.create_pieces(&create) {
+            let restore = CreatePieces { pos: cmd.from, kind: cmd.kind, count: cmd.count };
+            self.create_pieces(&restore)?;
+            return Err(e);
+        }
+        Ok(())
+    }
+
+    fn remove_pieces(&mut self, pos: QPos, kind: QKind, count: u32) -> Result<(), CmdError> {
+        let (shard, journal) = self.find_shard_mut(pos.pos, pos.region)?;
+        let removed = match shard {
+            Some(MutShard::Ordered(shard)) => shard.take(pos.suffix, kind, count, journal),
+            Some(MutShard::Unordered(shard)) => {
+                shard.take(Key { pos_suffix: pos.suffix, kind }, count, journal)
+            }
+            None => false,
+        };
+        if removed {
+            Ok(())
+        } else {
+            Err(CmdError::NotEnoughPieces(pos, kind))
         }
     }
 
     fn create_pieces(&mut self, cmd: &CreatePieces) -> Result<(), CmdError> {
         match self.find_or_create_shard_mut(cmd.pos.pos, cmd.pos.region)? {
-            MutShard::Ordered(ordered) => Self::create_ordered_pieces(cmd, ordered),
-            MutShard::Unordered(unordered) => Self::create_unordered_pieces(cmd, unordered),
+            (MutShard::Ordered(ordered), journal) => Self::create_ordered_pieces(cmd, ordered, journal),
+            (MutShard::Unordered(unordered), journal) => Self::create_unordered_pieces(cmd, unordered, journal),
         }
     }
 
-    fn create_ordered_pieces(cmd: &CreatePieces, ordered: &mut OrderedShard) -> Result<(), CmdError> {
-        Ok(())
+
+    fn create_ordered_pieces(cmd: &CreatePieces, ordered: &mut OrderedShard, journal: &mut Journal) -> Result<(), CmdError> {
+        if ordered.insert(cmd.pos.suffix, cmd.kind, cmd.count, journal) {
+            Ok(())
+        } else {
+            Err(CmdError::InvalidSlot(cmd.pos))
+        }
     }
 
-    fn create_unordered_pieces(cmd: &CreatePieces, shard: &mut UnorderedShard) -> Result<(), CmdError> {
-        let key = Key { pos_suffix: cmd.pos.suffix, kind: cmd.kind };
-        match shard.counts.entry(key) {
-            Entry::Occupied(mut e) => *(e.get_mut()) += cmd.count,
-            Entry::Vacant(e) => { e.insert(cmd.count); }
-        };
+    fn create_unordered_pieces(cmd: &CreatePieces, shard: &mut UnorderedShard, journal: &mut Journal) -> Result<(), CmdError> {
+        if shard.add(Key { pos_suffix: cmd.pos.suffix, kind: cmd.kind }, cmd.count, journal) {
+            Ok(())
+        } else {
+            Err(CmdError::TooManyPieces(cmd.pos, cmd.kind))
+        }
+    }
+
+    pub fn apply_diff(&mut self, diff: &Diff) -> Result<(), CmdError> {
+        for (pos, changes) in diff {
+            let rows = changes.removed.iter().map(|row| (row, false))
+                .chain(changes.added.iter().map(|row| (row, true)))
+                .chain(changes.changed.iter().map(|change| (&change.row, true)))
+                .chain(changes.reordered.iter().map(|change| (&change.row, true)));
+            for (row, keep) in rows {
+                let (shard, journal) = self.find_or_create_shard_mut(pos.pos, pos.region)?;
+                match (shard, keep) {
+                    (MutShard::Ordered(shard), true) => shard.set_row(row, journal),
+                    (MutShard::Ordered(shard), false) => shard.remove_row(row, journal),
+                    (MutShard::Unordered(shard), true) => shard.set_row(row, journal),
+                    (MutShard::Unordered(shard), false) => shard.remove_row(row, journal),
+                }
+            }
+        }
         Ok(())
     }
 
-    fn find_or_create_shard_mut(&mut self, pos: Pos, region: Region) -> Result<MutShard, CmdError> {
-        self.find_region_mut(pos)
-            .map(|s| s.find_or_create_shard_mut(pos, region))
+    fn find_or_create_shard_mut(&mut self, pos: Pos, region: Region) -> Result<(MutShard<'_>, &mut Journal), CmdError> {
+        let shard = self.state.shard_mut(&pos).ok_or(CmdError::NoSuchPos(pos))?;
+        Ok((shard.find_or_create_shard_mut(pos, region), &mut self.journal))
     }
 
-    fn find_shard_mut(&mut self, pos: Pos, region: Region) -> Result<Option<MutShard>, CmdError> {
-        self.find_region_mut(pos)
-            .map(|s| s.find_shard_mut(region))
+    fn find_shard_mut(&mut self, pos: Pos, region: Region) -> Result<(Option<MutShard<'_>>, &mut Journal), CmdError> {
+        let shard = self.state.shard_mut(&pos).ok_or(CmdError::NoSuchPos(pos))?;
+        Ok((shard.find_shard_mut(region), &mut self.journal))
     }
 
-    fn find_region_mut(&mut self, pos: Pos) -> Result<&mut Shard, CmdError> {
-        self.state.shards
-            .get_mut(&pos)
-            .ok_or(CmdError::NoSuchPos(pos))
+    /// The changes so far, found from the journal rather than by comparing
+    /// whole regions, so a command costs the same however full its region is.
+    fn changes(&self) -> Diff {
+        let mut diff = Diff::new();
+        let mut before = Vec::new();
+        let mut after = Vec::new();
+        for ((pos, kind), count) in self.journal.counts.iter() {
+            if *count > 0 {
+                before.push(ExportRow { pos: *pos, kind: *kind, count: *count });
+            }
+            after.extend(self.state.shard(&pos.pos).and_then(|s| s.find_row(*pos, *kind)));
+        }
+        diff.extend_between(before, after);
+
+        let mut before = Vec::new();
+        let mut after = Vec::new();
+        for (pos, kind) in self.journal.slots.iter() {
+            before.extend(kind.map(|kind| ExportRow { pos: *pos, kind, count: 1 }));
+            let now = self.state.shard(&pos.pos).and_then(|s| s.find_slot(*pos));
+            after.extend(now.map(|kind| ExportRow { pos: *pos, kind, count: 1 }));
+        }
+        diff.extend_between_slots(before, after);
+        diff
     }
 
-    pub fn commit(&mut self) {}
+    /// Completes the transaction, notifying subscribers of everything it changed.
+    pub fn commit(self) -> Diff {
+        let diff = self.changes();
+        for subscriber in &mut self.state.subscribers {
+            subscriber.notify(&diff);
+        }
+        diff
+    }
+
+    /// Abandons the transaction, undoing everything it changed without
+    /// notifying subscribers.
+    pub fn rollback(self) {
+        let undo = self.changes().inverse();
+        let mut tx = self.state.start_tx();
+        tx.apply_diff(&undo).ok();
+    }
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::diff::SlotChange;
     use crate::defs::{GameDefBuilder, KindDef, SuffixDef, PosDef};
     use crate::specs::GameSpec;
     use std::convert::TryInto;
     use crate::coords::Region;
+    use std::sync::{Arc, Mutex};
 
     struct Fixture {
         spec: GameSpec,
@@ -314,6 +729,7 @@ mod test {
             .pos(PosDef::bld("hand").hidden().separate())
             .pos(PosDef::bld("trick").separate())
             .pos(PosDef::bld("trump"))
+            .pos(PosDef::bld("stack").ordered())
             .build();
 
         let spec = def.try_into().unwrap();
@@ -343,12 +759,8 @@ mod test {
         }
 
         fn assert_rows(&self, expected_rows: &[ExportRow]) {
-            let mut actual_rows = self.state.export_rows();
-            actual_rows.sort();
-            assert_eq!(
-                expected_rows,
-                actual_rows.as_slice(),
-            )
+            let diff = Diff::between(expected_rows.to_vec(), self.state.export_rows());
+            assert!(diff.is_empty(), "unexpected changes to state:{:?}", diff);
         }
 
         fn create_pieces<P: AsRef<str>, K: AsRef<str>>(
@@ -371,14 +783,28 @@ mod test {
             })
         }
 
-        fn start_tx(&mut self) -> Transaction {
-            self.state.start_tx()
+        #[allow(clippy::too_many_arguments)]
+        fn move_pieces<P: AsRef<str>, Q: AsRef<str>, K: AsRef<str>>(
+            &self,
+            from: P,
+            from_suffix: i32,
+            from_region: u16,
+            to: Q,
+            to_suffix: i32,
+            to_region: u16,
+            kind: K,
+            kind_suffix: i32,
+            count: u32,
+        ) -> Cmd {
+            let from = self.row(from, from_suffix, from_region, &kind, kind_suffix, count);
+            let to = self.row(to, to_suffix, to_region, &kind, kind_suffix, count);
+            Cmd::MovePieces(MovePieces { from: from.pos, to: to.pos, kind: from.kind, count })
         }
 
-        fn apply(&mut self, cmd: &Cmd) {
+        fn apply(&mut self, cmd: &Cmd) -> Diff {
             let mut tx = self.state.start_tx();
-            tx.apply(&cmd);
-            tx.commit();
+            tx.apply(cmd).unwrap();
+            tx.commit()
         }
     }
 
@@ -390,9 +816,276 @@ mod test {
         fixture.apply(&fixture.create_pieces("deck", 0, 0, "card", 1, 3));
 
         fixture.assert_rows(
-            &vec![
+            &[
                 fixture.row("deck", 0, 0, "card", 1, 4)
             ]
         );
     }
+
+    #[test]
+    fn commit_returns_diff_of_transaction() {
+        let mut fixture = create_fixture();
+
+        let diff = fixture.apply(&fixture.create_pieces("deck", 0, 0, "card", 1, 1));
+        assert_eq!(
+            Diff::between(vec![], vec![fixture.row("deck", 0, 0, "card", 1, 1)]),
+            diff
+        );
+
+        let diff = fixture.apply(&fixture.create_pieces("deck", 0, 0, "card", 1, 3));
+        assert_eq!(
+            Diff::between(
+                vec![fixture.row("deck", 0, 0, "card", 1, 1)],
+                vec![fixture.row("deck", 0, 0, "card", 1, 4)],
+            ),
+            diff
+        );
+    }
+
+    #[test]
+    fn can_create_pieces_in_ordered_pos() {
+        let mut fixture = create_fixture();
+
+        fixture.apply(&fixture.create_pieces("stack", 0, 0, "card", 1, 2));
+        fixture.apply(&fixture.create_pieces("stack", 1, 0, "card", 2, 1));
+
+        fixture.assert_rows(
+            &[
+                fixture.row("stack", 0, 0, "card", 1, 1),
+                fixture.row("stack", 1, 0, "card", 2, 1),
+                fixture.row("stack", 2, 0, "card", 1, 1),
+            ]
+        );
+    }
+
+    #[test]
+    fn can_not_create_pieces_past_end_of_ordered_pos() {
+        let mut fixture = create_fixture();
+
+        let cmd = fixture.create_pieces("stack", 1, 0, "card", 1, 1);
+        let pos = fixture.row("stack", 1, 0, "card", 1, 1).pos;
+        let mut tx = fixture.state.start_tx();
+
+        assert_eq!(Err(CmdError::InvalidSlot(pos)), tx.apply(&cmd));
+    }
+
+    #[test]
+    fn can_not_overflow_slots_of_ordered_pos() {
+        let mut fixture = create_fixture();
+        fixture.apply(&fixture.create_pieces("stack", 0, 0, "card", 1, 1));
+
+        let too_many = fixture.create_pieces("stack", 0, 0, "card", 1, u32::MAX);
+        let top = fixture.row("stack", 0, 0, "card", 1, 1);
+        let mut tx = fixture.state.start_tx();
+
+        assert_eq!(Err(CmdError::InvalidSlot(top.pos)), tx.apply(&too_many));
+        let take = Cmd::MovePieces(MovePieces { from: top.pos, to: top.pos, kind: top.kind, count: u32::MAX });
+        assert_eq!(Err(CmdError::NotEnoughPieces(top.pos, top.kind)), tx.apply(&take));
+        assert!(tx.commit().is_empty());
+    }
+
+    #[test]
+    fn can_not_overflow_count_of_unordered_pos() {
+        let mut fixture = create_fixture();
+        fixture.apply(&fixture.create_pieces("deck", 0, 0, "card", 1, 1));
+
+        let too_many = fixture.create_pieces("deck", 0, 0, "card", 1, u32::MAX);
+        let row = fixture.row("deck", 0, 0, "card", 1, 1);
+        let mut tx = fixture.state.start_tx();
+
+        assert_eq!(Err(CmdError::TooManyPieces(row.pos, row.kind)), tx.apply(&too_many));
+        assert!(tx.commit().is_empty());
+    }
+
+    #[test]
+    fn can_move_pieces_between_positions() {
+        let mut fixture = create_fixture();
+
+        fixture.apply(&fixture.create_pieces("deck", 0, 0, "card", 1, 3));
+        fixture.apply(&fixture.create_pieces("stack", 0, 0, "card", 2, 1));
+        fixture.apply(&fixture.move_pieces("deck", 0, 0, "stack", 1, 0, "card", 1, 2));
+        fixture.apply(&fixture.move_pieces("stack", 0, 0, "hand", 0, 3, "card", 2, 1));
+
+        fixture.assert_rows(
+            &[
+                fixture.row("deck", 0, 0, "card", 1, 1),
+                fixture.row("hand", 0, 3, "card", 2, 1),
+                fixture.row("stack", 0, 0, "card", 1, 1),
+                fixture.row("stack", 1, 0, "card", 1, 1),
+            ]
+        );
+    }
+
+    #[test]
+    fn inverse_diff_undoes_commit() {
+        let mut fixture = create_fixture();
+        fixture.apply(&fixture.create_pieces("deck", 0, 0, "card", 1, 3));
+        fixture.apply(&fixture.create_pieces("stack", 0, 0, "card", 2, 1));
+        let before = fixture.state.clone();
+
+        let diff = fixture.apply(&fixture.move_pieces("deck", 0, 0, "stack", 0, 0, "card", 1, 2));
+        fixture.state.apply_diff(&diff.inverse()).unwrap();
+
+        assert!(fixture.state.diff(&before).is_empty());
+        assert_eq!(before.content_hash(), fixture.state.content_hash());
+    }
+
+    #[test]
+    fn can_not_move_missing_pieces() {
+        let mut fixture = create_fixture();
+        fixture.apply(&fixture.create_pieces("deck", 0, 0, "card", 1, 1));
+        fixture.apply(&fixture.create_pieces("stack", 0, 0, "card", 2, 1));
+
+        let too_many = fixture.move_pieces("deck", 0, 0, "trump", 0, 0, "card", 1, 2);
+        let wrong_slot = fixture.move_pieces("deck", 0, 0, "stack", 2, 0, "card", 1, 1);
+        let deck = fixture.row("deck", 0, 0, "card", 1, 1);
+        let mut tx = fixture.state.start_tx();
+
+        assert_eq!(Err(CmdError::NotEnoughPieces(deck.pos, deck.kind)), tx.apply(&too_many));
+        assert!(tx.apply(&wrong_slot).is_err());
+        assert!(tx.commit().is_empty());
+    }
+
+    #[test]
+    fn can_diff_and_patch_states() {
+        let mut fixture = create_fixture();
+        let mut other = State::new(&fixture.spec);
+
+        fixture.apply(&fixture.create_pieces("deck", 0, 0, "card", 1, 3));
+        fixture.apply(&fixture.create_pieces("stack", 0, 0, "card", 1, 1));
+        fixture.apply(&fixture.create_pieces("stack", 1, 0, "card", 2, 1));
+
+        let mut tx = other.start_tx();
+        tx.apply(&fixture.create_pieces("deck", 0, 0, "card", 1, 1)).unwrap();
+        tx.apply(&fixture.create_pieces("trump", 0, 0, "suit", 2, 1)).unwrap();
+        tx.apply(&fixture.create_pieces("stack", 0, 0, "card", 2, 1)).unwrap();
+        tx.apply(&fixture.create_pieces("stack", 1, 0, "card", 1, 1)).unwrap();
+        tx.commit();
+
+        let diff = fixture.state.diff(&other);
+
+        let deck = diff.get(&fixture.row("deck", 0, 0, "card", 1, 1).pos).unwrap();
+        assert_eq!(1, deck.changed.len());
+        assert_eq!(3, deck.changed[0].from);
+        let stack = diff.get(&fixture.row("stack", 0, 0, "card", 1, 1).pos).unwrap();
+        let from = fixture.row("stack", 0, 0, "card", 1, 1).kind;
+        assert_eq!(vec![SlotChange { row: fixture.row("stack", 0, 0, "card", 2, 1), from }], stack.reordered);
+        assert!(stack.removed.is_empty() && stack.added.is_empty());
+
+        fixture.state.apply_diff(&diff).unwrap();
+
+        assert!(fixture.state.diff(&other).is_empty());
+    }
+
+    #[test]
+    fn content_hash_is_independent_of_history() {
+        let mut fixture = create_fixture();
+        let mut other = State::new(&fixture.spec);
+        assert_eq!(fixture.state.content_hash(), other.content_hash());
+
+        fixture.apply(&fixture.create_pieces("deck", 0, 0, "card", 1, 2));
+        fixture.apply(&fixture.create_pieces("stack", 0, 0, "card", 2, 1));
+        fixture.apply(&fixture.create_pieces("stack", 0, 0, "card", 1, 1));
+        assert_ne!(fixture.state.content_hash(), other.content_hash());
+
+        let mut tx = other.start_tx();
+        tx.apply(&fixture.create_pieces("stack", 0, 0, "card", 2, 1)).unwrap();
+        tx.apply(&fixture.create_pieces("deck", 0, 0, "card", 1, 1)).unwrap();
+        tx.apply(&fixture.create_pieces("stack", 0, 0, "card", 1, 1)).unwrap();
+        tx.apply(&fixture.create_pieces("deck", 0, 0, "card", 1, 1)).unwrap();
+        tx.commit();
+        assert_eq!(fixture.state.content_hash(), other.content_hash());
+
+        let recomputed = fixture.state.export_rows()
+            .iter()
+            .fold(0, |hash, row| hash ^ row.zobrist_key());
+        assert_eq!(recomputed, fixture.state.content_hash());
+    }
+
+    #[test]
+    fn content_hash_tracks_applied_diffs() {
+        let mut fixture = create_fixture();
+        let mut other = State::new(&fixture.spec);
+
+        fixture.apply(&fixture.create_pieces("deck", 0, 0, "card", 1, 2));
+        fixture.apply(&fixture.create_pieces("stack", 0, 0, "card", 2, 1));
+
+        other.apply_diff(&other.diff(&fixture.state)).unwrap();
+        assert_eq!(fixture.state.content_hash(), other.content_hash());
+    }
+
+    #[test]
+    fn clones_are_independent() {
+        let mut fixture = create_fixture();
+        fixture.apply(&fixture.create_pieces("deck", 0, 0, "card", 1, 2));
+        fixture.apply(&fixture.create_pieces("stack", 0, 0, "card", 2, 1));
+
+        let mut clone = fixture.state.clone();
+        let mut tx = clone.start_tx();
+        tx.apply(&fixture.create_pieces("deck", 0, 0, "card", 1, 1)).unwrap();
+        tx.apply(&fixture.create_pieces("stack", 0, 0, "card", 3, 1)).unwrap();
+        tx.commit();
+
+        fixture.assert_rows(
+            &[
+                fixture.row("deck", 0, 0, "card", 1, 2),
+                fixture.row("stack", 0, 0, "card", 2, 1),
+            ]
+        );
+        assert_eq!(3, clone.export_rows().len());
+        assert_ne!(fixture.state.content_hash(), clone.content_hash());
+    }
+
+    #[test]
+    fn subscribers_are_notified_on_commit() {
+        let mut fixture = create_fixture();
+
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let sink = received.clone();
+        fixture.state.subscribe(move |diff: &Diff| sink.lock().unwrap().push(diff.clone()));
+
+        let diff = fixture.apply(&fixture.create_pieces("trick", 0, 2, "card", 7, 1));
+
+        assert_eq!(vec![diff], *received.lock().unwrap());
+    }
+
+    #[test]
+    fn rollback_restores_state_without_notifying() {
+        let mut fixture = create_fixture();
+        fixture.apply(&fixture.create_pieces("stack", 0, 0, "card", 1, 1));
+
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let sink = received.clone();
+        fixture.state.subscribe(move |diff: &Diff| sink.lock().unwrap().push(diff.clone()));
+
+        let in_stack = fixture.create_pieces("stack", 0, 0, "card", 2, 1);
+        let in_deck = fixture.create_pieces("deck", 0, 0, "card", 3, 2);
+        let mut tx = fixture.state.start_tx();
+        tx.apply(&in_stack).unwrap();
+        tx.apply(&in_deck).unwrap();
+        tx.rollback();
+
+        fixture.assert_rows(&[fixture.row("stack", 0, 0, "card", 1, 1)]);
+        assert!(received.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn player_rows_respect_hidden_and_separate() {
+        let mut fixture = create_fixture();
+
+        fixture.apply(&fixture.create_pieces("deck", 0, 0, "card", 1, 1));
+        fixture.apply(&fixture.create_pieces("hand", 0, 1, "card", 2, 1));
+        fixture.apply(&fixture.create_pieces("hand", 0, 2, "card", 3, 1));
+        fixture.apply(&fixture.create_pieces("trump", 0, 0, "card", 4, 1));
+
+        let mut rows = fixture.state.export_player_rows(&fixture.spec, PlayerNum(2));
+        rows.sort();
+        assert_eq!(
+            vec![
+                fixture.row("hand", 0, 2, "card", 3, 1),
+                fixture.row("trump", 0, 0, "card", 4, 1),
+            ],
+            rows
+        );
+    }
 }
\ No newline at end of file
@@ -0,0 +1,375 @@
+//! Property testing support, behind the `testing` feature.
+//!
+//! `arb_game` generates random valid game definitions together with random
+//! steps to play in them. Each step becomes a `Cmd` for the state it is
+//! played in, and `check_cmd` applies it and checks the state's invariants,
+//! so downstream games can run their own commands through the same checks.
+//!
+//! ```ignore
+//! proptest! {
+//!     #[test]
+//!     fn invariants_hold((def, steps) in arb_game()) {
+//!         let spec: GameSpec = def.try_into().unwrap();
+//!         run_steps(&spec, &steps).unwrap();
+//!     }
+//! }
+//! ```
+
+use std::collections::BTreeMap;
+
+use proptest::collection::vec;
+use proptest::prelude::*;
+use proptest::sample::Index;
+
+use crate::coords::{QKind, QPos, Region, Suffix};
+use crate::defs::{DimensionDef, GameDef, KindDef, PosDef, SuffixDef, SuffixProductDef, SuffixRangeDef};
+use crate::diff::Diff;
+use crate::specs::{GameSpec, KindSpec, PosSpec, SuffixSpec};
+use crate::state::{Cmd, CreatePieces, ExportRow, MovePieces, State};
+
+/// A broken invariant, found after applying `cmd`.
+#[derive(Debug, PartialEq, Clone)]
+pub enum Violation {
+    /// The number of pieces changed by other than the pieces created.
+    CountChanged { cmd: Cmd, expected: u64, found: u64 },
+    ZeroCountRow { cmd: Cmd, row: ExportRow },
+    /// An ordered position has an empty slot below an occupied one.
+    GapInOrderedPos { cmd: Cmd, pos: QPos },
+    /// A command that failed left the state changed.
+    FailedCmdChangedState { cmd: Cmd },
+    /// A state rebuilt from its exported rows differs from the original.
+    SnapshotMismatch { cmd: Cmd },
+    /// Applying the diff of the command, or its inverse, did not give the
+    /// state after, or before, the command.
+    DiffMismatch { cmd: Cmd },
+}
+
+/// `len` increasing ids from `first`, with random gaps between them.
+fn sparse_ids(first: std::ops::Range<u32>, len: usize) -> impl Strategy<Value=Vec<u32>> {
+    (first, vec(1..40u32, len)).prop_map(|(first, gaps)| {
+        gaps.into_iter()
+            .scan(first, |id, gap| {
+                let current = *id;
+                *id += gap;
+                Some(current)
+            })
+            .collect()
+    })
+}
+
+fn suffix_defs() -> impl Strategy<Value=(Option<SuffixRangeDef>, Vec<SuffixDef>)> {
+    prop_oneof![
+        Just((None, Vec::new())),
+        (-3..3i32, 1..6i32).prop_map(|(min, len)| (Some(SuffixRangeDef { min, max: min + len }), Vec::new())),
+        (1..4usize).prop_flat_map(|n| sparse_ids(0..3, n)).prop_map(|ids| {
+            let suffixes = ids.into_iter()
+                .map(|id| SuffixDef {
+                    label: format!("suffix{}", id),
+                    id: Some(id),
+                    aliases: Vec::new(),
+                    names: BTreeMap::new(),
+                    attributes: BTreeMap::new(),
+                })
+                .collect();
+            (None, suffixes)
+        }),
+    ]
+}
+
+fn suffix_product_def() -> impl Strategy<Value=SuffixProductDef> {
+    vec(1..4usize, 1..3).prop_map(|sizes| SuffixProductDef {
+        dimensions: sizes.into_iter()
+            .enumerate()
+            .map(|(d, size)| DimensionDef {
+                label: format!("dimension{}", d + 1),
+                values: (1..=size).map(|v| format!("value{}", v)).collect(),
+            })
+            .collect(),
+    })
+}
+
+/// The suffixes of a kind, which unlike a position's may be a product.
+fn kind_suffix_defs() -> impl Strategy<Value=(Option<SuffixRangeDef>, Vec<SuffixDef>, Option<SuffixProductDef>)> {
+    prop_oneof![
+        3 => suffix_defs().prop_map(|(range, suffixes)| (range, suffixes, None)),
+        1 => suffix_product_def().prop_map(|product| (None, Vec::new(), Some(product))),
+    ]
+}
+
+/// Random valid game definitions with up to three kinds and four positions,
+/// numbered with gaps between their ids.
+pub fn arb_game_def() -> impl Strategy<Value=GameDef> {
+    let players = (1..5u32).prop_flat_map(|max| (1..=max, Just(max)));
+    let kinds = vec(kind_suffix_defs(), 1..4)
+        .prop_flat_map(|kinds| {
+            let len = kinds.len();
+            (Just(kinds), sparse_ids(1..20, len))
+        });
+    let positions = vec((suffix_defs(), any::<bool>(), any::<bool>(), any::<bool>()), 1..5)
+        .prop_flat_map(|positions| {
+            let len = positions.len();
+            (Just(positions), sparse_ids(1..20, len))
+        });
+    (players, kinds, positions).prop_map(|((min_players, max_players), (kinds, kind_ids), (positions, pos_ids))| GameDef {
+        label: "arbitrary".to_owned(),
+        min_players,
+        max_players,
+        kind_defs: kinds.into_iter()
+            .zip(kind_ids)
+            .enumerate()
+            .map(|(i, ((suffix_range, suffixes, suffix_product), id))| KindDef {
+                label: format!("kind{}", i + 1),
+                id: Some(id),
+                aliases: Vec::new(),
+                names: BTreeMap::new(),
+                attributes: BTreeMap::new(),
+                suffix_range,
+                suffixes,
+                suffix_product,
+            })
+            .collect(),
+        pos_defs: positions.into_iter()
+            .zip(pos_ids)
+            .enumerate()
+            .map(|(i, (((suffix_range, suffixes), separate, ordered, hidden), id))| {
+                // The suffix of an ordered position is its slot.
+                let (suffix_range, suffixes) = if ordered { (None, Vec::new()) } else { (suffix_range, suffixes) };
+                PosDef {
+                    label: format!("pos{}", i + 1),
+                    id: Some(id),
+                    aliases: Vec::new(),
+                    names: BTreeMap::new(),
+                    suffix_range,
+                    suffixes,
+                    separate,
+                    ordered,
+                    hidden,
+                }
+            })
+            .collect(),
+        migrations: Vec::new(),
+    })
+}
+
+/// Where pieces go: indexes into the spec's positions and their suffixes,
+/// and a region that is not checked against the spec's players.
+#[derive(Debug, Clone)]
+pub struct Target {
+    pub pos: Index,
+    pub region: u8,
+    pub suffix: Index,
+}
+
+/// A move in a random game, turned into a `Cmd` by `to_cmd` once the state
+/// it is played in is known.
+#[derive(Debug, Clone)]
+pub enum Step {
+    Create { to: Target, kind: Index, suffix: Index, count: u32 },
+    /// Moves some of the pieces of one of the rows of the state.
+    Move { row: Index, to: Target, count: u32 },
+}
+
+/// Mostly regions of the first few players, sometimes any region at all.
+fn arb_target() -> impl Strategy<Value=Target> {
+    let region = prop_oneof![8 => 0..4u8, 1 => any::<u8>()];
+    (any::<Index>(), region, any::<Index>())
+        .prop_map(|(pos, region, suffix)| Target { pos, region, suffix })
+}
+
+/// Mostly a few pieces, sometimes more than fit a slot number. Counts that
+/// fit one but are large are left out, as filling that many slots is slow.
+fn arb_count() -> impl Strategy<Value=u32> {
+    prop_oneof![8 => 1..4u32, 1 => (i32::MAX as u32 + 1)..=u32::MAX]
+}
+
+/// Random steps, moving pieces twice as often as creating them.
+pub fn arb_steps(len: std::ops::Range<usize>) -> impl Strategy<Value=Vec<Step>> {
+    let step = prop_oneof![
+        1 => (arb_target(), any::<Index>(), any::<Index>(), arb_count())
+            .prop_map(|(to, kind, suffix, count)| Step::Create { to, kind, suffix, count }),
+        2 => (any::<Index>(), arb_target(), 1..4u32)
+            .prop_map(|(row, to, count)| Step::Move { row, to, count }),
+    ];
+    vec(step, len)
+}
+
+/// A random game definition and up to thirty steps to play in it.
+pub fn arb_game() -> impl Strategy<Value=(GameDef, Vec<Step>)> {
+    (arb_game_def(), arb_steps(0..30))
+}
+
+fn valid_suffixes(suffixes: &SuffixSpec) -> Vec<Suffix> {
+    match suffixes {
+        SuffixSpec::Empty => vec![Suffix(0)],
+        SuffixSpec::Range(range) => (range.min.0..=range.max.0).map(Suffix).collect(),
+        SuffixSpec::Table(_) | SuffixSpec::Product(_) => {
+            suffixes.table().into_iter().flatten().map(|r| r.suffix).collect()
+        }
+    }
+}
+
+impl Target {
+    fn resolve(&self, spec: &GameSpec, state: &State) -> QPos {
+        let positions: Vec<&PosSpec> = spec.positions().collect();
+        let pos = *self.pos.get(&positions);
+
+        let region = Region(self.region as u16);
+        let suffix = if pos.ordered {
+            // One past the top slot is the first invalid slot, which is
+            // worth trying too.
+            let len = state.export_rows()
+                .iter()
+                .filter(|r| r.pos.pos == pos.id && r.pos.region == region)
+                .count();
+            Suffix(self.suffix.index(len + 2) as i32)
+        } else {
+            *self.suffix.get(&valid_suffixes(&pos.suffixes))
+        };
+        QPos { pos: pos.id, region, suffix }
+    }
+}
+
+impl Step {
+    /// The command this step makes in `state`, or `None` for a move when
+    /// there are no pieces to move.
+    pub fn to_cmd(&self, spec: &GameSpec, state: &State) -> Option<Cmd> {
+        match self {
+            Step::Create { to, kind, suffix, count } => {
+                let kinds: Vec<&KindSpec> = spec.kinds().collect();
+                let kind = *kind.get(&kinds);
+                let suffix = *suffix.get(&valid_suffixes(&kind.suffixes));
+                Some(Cmd::CreatePieces(CreatePieces {
+                    pos: to.resolve(spec, state),
+                    kind: QKind { kind: kind.id, suffix },
+                    count: *count,
+                }))
+            }
+            Step::Move { row, to, count } => {
+                let mut rows = state.export_rows();
+                if rows.is_empty() {
+                    return None;
+                }
+                rows.sort();
+                let row = row.get(&rows);
+                Some(Cmd::MovePieces(MovePieces {
+                    from: row.pos,
+                    to: to.resolve(spec, state),
+                    kind: row.kind,
+                    count: 1 + (count - 1) % row.count,
+                }))
+            }
+        }
+    }
+}
+
+fn total(rows: &[ExportRow]) -> u64 {
+    rows.iter().map(|r| r.count as u64).sum()
+}
+
+fn sorted_rows(state: &State) -> Vec<ExportRow> {
+    let mut rows = state.export_rows();
+    rows.sort();
+    rows
+}
+
+/// Applies `cmd` to `state` in its own transaction and checks that the
+/// state's invariants still hold.
+pub fn check_cmd(spec: &GameSpec, state: &mut State, cmd: &Cmd) -> Result<(), Violation> {
+    let before = state.clone();
+    let before_rows = sorted_rows(&before);
+
+    let mut tx = state.start_tx();
+    let result = tx.apply(cmd);
+    tx.commit();
+    let rows = sorted_rows(state);
+
+    if result.is_err() {
+        if rows != before_rows || state.content_hash() != before.content_hash() {
+            return Err(Violation::FailedCmdChangedState { cmd: cmd.clone() });
+        }
+        return Ok(());
+    }
+
+    let created = match cmd {
+        Cmd::CreatePieces(create) => create.count as u64,
+        Cmd::MovePieces(_) => 0,
+    };
+    if total(&rows) != total(&before_rows) + created {
+        return Err(Violation::CountChanged {
+            cmd: cmd.clone(),
+            expected: total(&before_rows) + created,
+            found: total(&rows),
+        });
+    }
+
+    if let Some(row) = rows.iter().find(|r| r.count == 0) {
+        return Err(Violation::ZeroCountRow { cmd: cmd.clone(), row: *row });
+    }
+
+    let mut slots: BTreeMap<(QPos, Region), Vec<i32>> = BTreeMap::new();
+    for row in &rows {
+        if spec.pos_specs.find(&row.pos.pos).is_some_and(|s| s.ordered) {
+            let key = QPos { suffix: Suffix(0), ..row.pos };
+            slots.entry((key, row.pos.region)).or_default().push(row.pos.suffix.0);
+        }
+    }
+    for ((pos, _), mut slots) in slots {
+        slots.sort_unstable();
+        if slots.iter().enumerate().any(|(i, slot)| *slot != i as i32) {
+            return Err(Violation::GapInOrderedPos { cmd: cmd.clone(), pos });
+        }
+    }
+
+    let mut restored = State::new(spec);
+    let snapshot_ok = restored.apply_diff(&Diff::between(Vec::new(), rows.clone())).is_ok()
+        && sorted_rows(&restored) == rows
+        && restored.content_hash() == state.content_hash();
+    if !snapshot_ok {
+        return Err(Violation::SnapshotMismatch { cmd: cmd.clone() });
+    }
+
+    let diff = before.diff(state);
+    let mut forward = before.clone();
+    let mut backward = state.clone();
+    let diff_ok = forward.apply_diff(&diff).is_ok()
+        && sorted_rows(&forward) == rows
+        && backward.apply_diff(&diff.inverse()).is_ok()
+        && sorted_rows(&backward) == before_rows;
+    if !diff_ok {
+        return Err(Violation::DiffMismatch { cmd: cmd.clone() });
+    }
+    Ok(())
+}
+
+/// Plays `steps` in a new state of `spec`, checking invariants after each.
+pub fn run_steps(spec: &GameSpec, steps: &[Step]) -> Result<State, Violation> {
+    let mut state = State::new(spec);
+    for step in steps {
+        if let Some(cmd) = step.to_cmd(spec, &state) {
+            check_cmd(spec, &mut state, &cmd)?;
+        }
+    }
+    Ok(state)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::convert::TryInto;
+
+    proptest! {
+        #[test]
+        fn arbitrary_defs_are_valid(def in arb_game_def()) {
+            let spec: Result<GameSpec, _> = def.try_into();
+            prop_assert!(spec.is_ok());
+        }
+
+        #[test]
+        fn invariants_hold((def, steps) in arb_game()) {
+            let spec: GameSpec = def.try_into().unwrap();
+            if let Err(violation) = run_steps(&spec, &steps) {
+                prop_assert!(false, "{:?}", violation);
+            }
+        }
+    }
+}
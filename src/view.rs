@@ -0,0 +1,269 @@
+use std::collections::BTreeMap;
+
+use rand::seq::SliceRandom;
+use rand::Rng;
+
+use crate::coords::{QKind, QPos};
+use crate::diff::Diff;
+use crate::specs::{GameSpec, PlayerNum};
+use crate::state::{ExportRow, State};
+
+/// The number of pieces at a position the player cannot see into, at most
+/// `u32::MAX`.
+#[derive(Debug, PartialEq, Eq, Ord, PartialOrd, Copy, Clone)]
+pub struct HiddenCount {
+    pub pos: QPos,
+    pub count: u32,
+}
+
+/// Everything one player can observe about a state.
+#[derive(Debug, PartialEq, Clone)]
+pub struct PlayerView {
+    pub player: PlayerNum,
+    pub rows: Vec<ExportRow>,
+    pub hidden: Vec<HiddenCount>,
+}
+
+impl State {
+    pub fn player_view(&self, spec: &GameSpec, player: PlayerNum) -> PlayerView {
+        let mut rows = Vec::new();
+        let mut hidden: BTreeMap<QPos, u32> = BTreeMap::new();
+        for row in self.export_rows() {
+            if spec.is_visible(&row.pos, player) {
+                rows.push(row);
+            } else {
+                let count = hidden.entry(row.pos).or_insert(0);
+                *count = count.saturating_add(row.count);
+            }
+        }
+        rows.sort();
+        PlayerView {
+            player,
+            rows,
+            hidden: hidden.into_iter()
+                .map(|(pos, count)| HiddenCount { pos, count })
+                .collect(),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum DeterminizeError {
+    /// More of a piece has been seen (or is known) than exists in the game.
+    TooManySeen(QKind),
+    /// A known piece was placed somewhere the player can see, or has no room for it.
+    InvalidKnown(ExportRow),
+    /// The unseen pieces do not fill the hidden positions.
+    CountMismatch { unseen: u32, hidden: u32 },
+    /// There are more than `u32::MAX` of a piece, or hidden pieces in all.
+    TooManyPieces,
+}
+
+/// Samples full states that are consistent with a player's view.
+pub struct Determinizer {
+    seen: Vec<ExportRow>,
+    known: Vec<ExportRow>,
+    slots: Vec<HiddenCount>,
+    unseen: Vec<QKind>,
+}
+
+impl Determinizer {
+    /// `totals` is how many of each piece exist in the whole game.
+    pub fn new(view: &PlayerView, totals: &[(QKind, u32)]) -> Result<Determinizer, DeterminizeError> {
+        Determinizer::with_known(view, totals, &[])
+    }
+
+    /// As `new`, but with pieces the player knows to be at hidden positions,
+    /// for example cards they passed to an opponent.
+    pub fn with_known(
+        view: &PlayerView,
+        totals: &[(QKind, u32)],
+        known: &[ExportRow],
+    ) -> Result<Determinizer, DeterminizeError> {
+        let mut remaining: BTreeMap<QKind, u32> = BTreeMap::new();
+        for (kind, count) in totals {
+            let remaining = remaining.entry(*kind).or_insert(0);
+            *remaining = remaining.checked_add(*count).ok_or(DeterminizeError::TooManyPieces)?;
+        }
+
+        let mut slots: BTreeMap<QPos, u32> = view.hidden
+            .iter()
+            .map(|h| (h.pos, h.count))
+            .collect();
+        for row in known {
+            let slot = slots.get_mut(&row.pos)
+                .filter(|count| **count >= row.count)
+                .ok_or(DeterminizeError::InvalidKnown(*row))?;
+            *slot -= row.count;
+        }
+
+        for row in view.rows.iter().chain(known) {
+            let count = remaining.get_mut(&row.kind)
+                .filter(|count| **count >= row.count)
+                .ok_or(DeterminizeError::TooManySeen(row.kind))?;
+            *count -= row.count;
+        }
+
+        let mut unseen = Vec::new();
+        for (kind, count) in remaining {
+            unseen.resize(unseen.len() + count as usize, kind);
+        }
+
+        let hidden = slots.values()
+            .try_fold(0u32, |sum, count| sum.checked_add(*count))
+            .ok_or(DeterminizeError::TooManyPieces)?;
+        if unseen.len() as u32 != hidden {
+            return Err(DeterminizeError::CountMismatch { unseen: unseen.len() as u32, hidden });
+        }
+
+        Ok(Determinizer {
+            seen: view.rows.clone(),
+            known: known.to_vec(),
+            slots: slots.into_iter()
+                .map(|(pos, count)| HiddenCount { pos, count })
+                .collect(),
+            unseen,
+        })
+    }
+
+    /// A random state the player cannot tell apart from the one they are in.
+    pub fn sample<R: Rng>(&self, spec: &GameSpec, rng: &mut R) -> State {
+        let mut pool = self.unseen.clone();
+        pool.shuffle(rng);
+
+        let mut dealt: BTreeMap<(QPos, QKind), u32> = BTreeMap::new();
+        for row in self.seen.iter().chain(&self.known) {
+            *dealt.entry((row.pos, row.kind)).or_insert(0) += row.count;
+        }
+        let mut pieces = pool.into_iter();
+        for slot in &self.slots {
+            for kind in pieces.by_ref().take(slot.count as usize) {
+                *dealt.entry((slot.pos, kind)).or_insert(0) += 1;
+            }
+        }
+
+        let rows = dealt.into_iter()
+            .map(|((pos, kind), count)| ExportRow { pos, kind, count })
+            .collect();
+        let mut state = State::new(spec);
+        state.apply_diff(&Diff::between(Vec::new(), rows))
+            .expect("rows come from positions in the view");
+        state
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::coords::{Region, Suffix};
+    use crate::defs::{GameDefBuilder, KindDef, PosDef};
+    use crate::state::{Cmd, CreatePieces};
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+    use std::convert::TryInto;
+
+    struct Fixture {
+        spec: GameSpec,
+        state: State,
+        totals: Vec<(QKind, u32)>,
+    }
+
+    fn card(n: i32) -> QKind {
+        QKind { kind: 1.try_into().unwrap(), suffix: Suffix(n) }
+    }
+
+    fn qpos(pos: u32, region: u16) -> QPos {
+        QPos { pos: pos.try_into().unwrap(), region: Region(region), suffix: Suffix(0) }
+    }
+
+    /// Twelve cards: four in the deck and four in each of two hands.
+    fn create_fixture() -> Fixture {
+        let spec: GameSpec = GameDefBuilder::bld("whist")
+            .kind(KindDef::bld("card").suffix_range(1, 52))
+            .pos(PosDef::bld("deck").hidden())
+            .pos(PosDef::bld("hand").hidden().separate())
+            .pos(PosDef::bld("trick"))
+            .build()
+            .try_into()
+            .unwrap();
+
+        let mut state = State::new(&spec);
+        let mut tx = state.start_tx();
+        for n in 1..=12 {
+            let pos = if n <= 4 { qpos(1, 0) } else { qpos(2, (n % 2) as u16) };
+            tx.apply(&Cmd::CreatePieces(CreatePieces { pos, kind: card(n), count: 1 })).unwrap();
+        }
+        tx.apply(&Cmd::CreatePieces(CreatePieces { pos: qpos(3, 0), kind: card(13), count: 1 }))
+            .unwrap();
+        tx.commit();
+
+        let totals = (1..=13).map(|n| (card(n), 1)).collect();
+        Fixture { spec, state, totals }
+    }
+
+    fn count_at(state: &State, pos: QPos) -> u32 {
+        state.export_rows().iter().filter(|r| r.pos == pos).map(|r| r.count).sum()
+    }
+
+    #[test]
+    fn view_hides_what_player_can_not_see() {
+        let fixture = create_fixture();
+        let view = fixture.state.player_view(&fixture.spec, PlayerNum(1));
+
+        assert_eq!(5, view.rows.len());
+        assert_eq!(
+            vec![
+                HiddenCount { pos: qpos(1, 0), count: 4 },
+                HiddenCount { pos: qpos(2, 0), count: 4 },
+            ],
+            view.hidden
+        );
+    }
+
+    #[test]
+    fn samples_are_consistent_with_view() {
+        let fixture = create_fixture();
+        let view = fixture.state.player_view(&fixture.spec, PlayerNum(1));
+        let determinizer = Determinizer::new(&view, &fixture.totals).unwrap();
+
+        let mut rng = StdRng::seed_from_u64(7);
+        for _ in 0..10 {
+            let world = determinizer.sample(&fixture.spec, &mut rng);
+            assert_eq!(view, world.player_view(&fixture.spec, PlayerNum(1)));
+
+            let mut kinds: Vec<QKind> = world.export_rows().iter().map(|r| r.kind).collect();
+            kinds.sort();
+            assert_eq!((1..=13).map(card).collect::<Vec<_>>(), kinds);
+        }
+    }
+
+    #[test]
+    fn samples_respect_known_pieces() {
+        let fixture = create_fixture();
+        let view = fixture.state.player_view(&fixture.spec, PlayerNum(1));
+        let known = ExportRow { pos: qpos(2, 0), kind: card(6), count: 1 };
+        let determinizer = Determinizer::with_known(&view, &fixture.totals, &[known]).unwrap();
+
+        let mut rng = StdRng::seed_from_u64(7);
+        for _ in 0..10 {
+            let world = determinizer.sample(&fixture.spec, &mut rng);
+            assert!(world.export_rows().contains(&known));
+            assert_eq!(4, count_at(&world, qpos(2, 0)));
+        }
+    }
+
+    #[test]
+    fn can_not_determinize_with_too_few_pieces() {
+        let fixture = create_fixture();
+        let view = fixture.state.player_view(&fixture.spec, PlayerNum(1));
+
+        assert_eq!(
+            Err(DeterminizeError::TooManySeen(card(13))),
+            Determinizer::new(&view, &fixture.totals[..12]).map(|_| ())
+        );
+        assert_eq!(
+            Err(DeterminizeError::CountMismatch { unseen: 7, hidden: 8 }),
+            Determinizer::new(&view, &fixture.totals[1..]).map(|_| ())
+        );
+    }
+}